@@ -1,159 +1,4829 @@
-use std::ptr;
-use std::slice;
-use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
-use std::thread;
-use std::time::{Duration, Instant};
-use libc::{shm_open, ftruncate, mmap, munmap, close, shm_unlink};
-use libc::{O_CREAT, O_RDWR, PROT_READ, PROT_WRITE, MAP_SHARED};
-use std::ffi::CString;
-
-const SHM_NAME: &str = "/low_latency_shm";
-const BUFFER_SIZE: usize = 1024 * 1024; // 1 MB shared memory
-const SLOT_SIZE: usize = 128;           // Fixed-size message slot
-const NUM_PRODUCERS: usize = 1;
-const NUM_CONSUMERS: usize = 1;
-
-struct SharedRingBuffer {
-    buffer: *mut u8,
-    write_idx: AtomicUsize,
-    read_idx: AtomicUsize,
-}
-
-unsafe impl Send for SharedRingBuffer {}
-unsafe impl Sync for SharedRingBuffer {}
-
-impl SharedRingBuffer {
-    fn new(name: &str) -> Result<Self, String> {
-        let name = CString::new(name).unwrap();
-        let fd = unsafe { shm_open(name.as_ptr(), O_CREAT | O_RDWR, 0o666) };
-        if fd == -1 {
-            return Err("Failed to create shared memory".to_string());
-        }
-
-        unsafe { ftruncate(fd, BUFFER_SIZE as i64) };
-
-        let addr = unsafe { mmap(ptr::null_mut(), BUFFER_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0) };
-        if addr == libc::MAP_FAILED {
-            return Err("Failed to map shared memory".to_string());
-        }
-
-        unsafe { close(fd) };
-
-        Ok(Self {
-            buffer: addr as *mut u8,
-            write_idx: AtomicUsize::new(0),
-            read_idx: AtomicUsize::new(0),
-        })
-    }
-
-    #[inline(always)]
-    fn is_full(&self) -> bool {
-        let read_idx = self.read_idx.load(Ordering::Relaxed);
-        let write_idx = self.write_idx.load(Ordering::Relaxed);
-        (write_idx.wrapping_sub(read_idx) / SLOT_SIZE) >= (BUFFER_SIZE / SLOT_SIZE)
-    }
-
-    #[inline(always)]
-    fn is_empty(&self) -> bool {
-        self.read_idx.load(Ordering::Relaxed) == self.write_idx.load(Ordering::Acquire)
-    }
-
-    fn write_message(&self, message: &[u8]) -> Result<(), String> {
-        while self.is_full() {
-            spin_wait(Duration::from_micros(5));
-        }
-
-        let write_idx = self.write_idx.load(Ordering::Relaxed);
-        let start = write_idx % BUFFER_SIZE;
-
-        unsafe {
-            let buffer = slice::from_raw_parts_mut(self.buffer, BUFFER_SIZE);
-            buffer[start..start + 4].copy_from_slice(&(message.len() as u32).to_le_bytes());
-            buffer[start + 4..start + 4 + message.len()].copy_from_slice(message);
-        }
-
-        self.write_idx.store(write_idx + SLOT_SIZE, Ordering::Release);
-        Ok(())
-    }
-
-    fn read_message(&self) -> Result<Vec<u8>, String> {
-        while self.is_empty() {
-            spin_wait(Duration::from_micros(1));
-        }
-
-        let read_idx = self.read_idx.load(Ordering::Relaxed);
-        let start = read_idx % BUFFER_SIZE;
-
-        let message = unsafe {
-            let buffer = slice::from_raw_parts(self.buffer, BUFFER_SIZE);
-            let mut len_bytes = [0u8; 4];
-            len_bytes.copy_from_slice(&buffer[start..start + 4]);
-            let message_len = u32::from_le_bytes(len_bytes) as usize;
-
-            if message_len > SLOT_SIZE - 4 {
-                return Err("Corrupted message length".to_string());
-            }
-
-            buffer[start + 4..start + 4 + message_len].to_vec()
-        };
-
-        self.read_idx.store(read_idx + SLOT_SIZE, Ordering::Release);
-        Ok(message)
-    }
-}
-
-impl Drop for SharedRingBuffer {
-    fn drop(&mut self) {
-        unsafe {
-            munmap(self.buffer as *mut libc::c_void, BUFFER_SIZE);
-            let name = CString::new(SHM_NAME).unwrap();
-            shm_unlink(name.as_ptr());
-        }
-    }
-}
-
-fn producer(_id: usize, ring_buffer: Arc<SharedRingBuffer>) {
-    let mut message_count = 0;
-    loop {
-        let message = format!("Received {}", message_count).into_bytes();
-        if ring_buffer.write_message(&message).is_ok() {
-            message_count += 1;
-        }
-    }
-}
-
-fn consumer(_id: usize, ring_buffer: Arc<SharedRingBuffer>) {
-    loop {
-        if let Ok(message) = ring_buffer.read_message() {
-            println!("{}", String::from_utf8_lossy(&message));
-        }
-    }
-}
-
-// Low-latency CPU spin loop
-#[inline(always)]
-fn spin_wait(duration: Duration) {
-    let start = Instant::now();
-    while start.elapsed() < duration {
-        std::hint::spin_loop();
-    }
-}
-
-fn main() {
-    let ring_buffer = Arc::new(SharedRingBuffer::new(SHM_NAME)
-        .expect("Failed to create shared ring buffer"));
-
-    let mut producer_handles = vec![];
-    for i in 0..NUM_PRODUCERS {
-        let ring_buffer = Arc::clone(&ring_buffer);
-        producer_handles.push(thread::spawn(move || producer(i, ring_buffer)));
-    }
-
-    let mut consumer_handles = vec![];
-    for i in 0..NUM_CONSUMERS {
-        let ring_buffer = Arc::clone(&ring_buffer);
-        consumer_handles.push(thread::spawn(move || consumer(i, ring_buffer)));
-    }
-
-    thread::sleep(Duration::from_secs(10));
-}
+#![allow(dead_code)] // Not every ring method is exercised by the demo in main
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::ptr;
+use std::sync::{Arc, Mutex, OnceLock, atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering}};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use libc::{shm_open, ftruncate, fstat, mmap, munmap, close, shm_unlink};
+use libc::{O_CREAT, O_EXCL, O_RDONLY, O_RDWR, PROT_READ, PROT_WRITE, MAP_SHARED};
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+const SHM_NAME: &str = "/low_latency_shm";
+const BUFFER_SIZE: usize = 1024 * 1024; // 1 MB shared memory
+const SLOT_SIZE: usize = 128;           // Fixed-size message slot
+const HEADER_SIZE: usize = std::mem::size_of::<Header>().next_multiple_of(CACHE_LINE); // Shared header, in whole lines
+const PAGE_SIZE: usize = 4096;
+const SLOT_HEADER_MAX: usize = 16; // Largest `SlotHeader::SIZE`
+const NO_HELD_SLOT: u64 = u64::MAX; // `SharedRingBuffer::held` when no slot is lent out
+const CONTROL_SIZE: usize = PAGE_SIZE; // Control segment of a split ring: the header and the user area
+const USER_HEADER_MAX: usize = CONTROL_SIZE - HEADER_SIZE; // Largest `RingConfig::user_header`
+const CACHE_LINE: usize = std::mem::align_of::<CachePadded<()>>(); // See `CachePadded`
+const DATA_OFFSET: usize = PAGE_SIZE;   // Header padded to a page so the data region (and the mirror's mmap offset) is page aligned
+const NUM_PRODUCERS: usize = 1;
+const NUM_CONSUMERS: usize = 1;
+const OPEN_TIMEOUT: Duration = Duration::from_secs(5); // How long `open` waits for the creator
+const WRITE_SPIN: Duration = Duration::from_micros(5); // Default spin between checks of a full ring
+const READ_SPIN: Duration = Duration::from_micros(1);  // Default spin between checks of an empty ring
+const E2E_MESSAGES: usize = 100_000;                   // Messages sent by the `e2e` self-check
+const RACE_CREATORS: usize = 8;                        // Processes racing in the `create-race` self-check
+const FAN_IN_SENDERS: usize = 4;                       // Threads sending at once in the `fan-in` self-check
+const RACE_HOLD: Duration = Duration::from_secs(1);    // How long the winner keeps the ring, so every racer finds it
+const FUZZ_ROUNDS: usize = 1_000_000;                  // Corrupted reads tried by the `fuzz-read` self-check
+const FAULT_SLOTS: usize = DATA_OFFSET / SLOT_SIZE;    // Ring of the `fault-inject` self-check, small but mirrorable
+const SPURIOUS_WAKEUPS: usize = 1_000;                 // Empty unparks sent by the `spurious-wakeup` self-check
+const CAPACITY_SLOTS: usize = DATA_OFFSET / SLOT_SIZE; // Ring filled to the last slot by the `capacity` self-check
+const WRAP_SLOTS: usize = DATA_OFFSET / SLOT_SIZE;     // Ring of the `index-wrap` self-check, a power of two in size
+const MIGRATE_HEADER: &[u8] = b"migrated";             // User header carried over by the `migrate` self-check
+const BENCH_MESSAGES: usize = 10_000_000;              // Messages sent by the `bench` throughput run
+const BENCH_SAMPLES: usize = 100_000;                  // Messages timed by the `bench` latency run
+const BENCH_PRODUCERS: usize = 4;                      // Senders in the `bench` multi-producer run
+#[cfg(feature = "affinity")]
+const PRODUCER_FIRST_CORE: usize = 0;                // Producers pinned to cores 0..NUM_PRODUCERS
+#[cfg(feature = "affinity")]
+const CONSUMER_FIRST_CORE: usize = NUM_PRODUCERS;    // Consumers pinned right after the producers
+
+const INITIALIZED: u32 = 1;
+
+// mmap flags for `RingConfig::populate`, none where the platform can't populate
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const POPULATE_FLAGS: libc::c_int = libc::MAP_POPULATE;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const POPULATE_FLAGS: libc::c_int = 0;
+
+// The index scheme assumes no slot straddles the end of the data region
+const _: () = assert!(BUFFER_SIZE.is_multiple_of(SLOT_SIZE), "SLOT_SIZE must divide BUFFER_SIZE");
+
+// Errors reported by the ring buffer
+#[derive(Debug)]
+enum Error {
+    // A shm_open/ftruncate/mmap call failed, for a reason not covered below
+    Os(&'static str, io::Error),
+    // The segment didn't fit: `/dev/shm` (or the file system holding a ring file)
+    // is full (`ENOSPC`), so e.g. removing stale segments may help
+    OutOfSpace(&'static str),
+    // The process may not create or open the segment (`EACCES`, `EPERM`)
+    PermissionDenied(&'static str),
+    // There's no segment or ring file of that name (`ENOENT`)
+    NotFound(&'static str),
+    // The creator didn't finish setting up the segment in time
+    TimedOut,
+    // The shared header or a slot holds values normal operation can't produce
+    Corrupt(&'static str),
+    // The message doesn't fit in a slot next to its length prefix
+    MessageTooLarge { len: usize, max: usize },
+    // Rejected ring layout, e.g. a slot size that doesn't divide the buffer size
+    InvalidConfig(&'static str),
+    // The ring was full and its `FullPolicy` said to drop the message
+    Full,
+    // A segment name the platform would reject, see `RingName`
+    InvalidName(&'static str),
+    // `set_read_cursor` was given a message that is no longer, or not yet, in the ring
+    CursorOutOfRange { seq: u64, oldest: u64, next: u64 },
+    // A message for a `Framing::Fixed` ring that isn't exactly the fixed length
+    WrongLength { len: usize, expected: usize },
+    // `write_slot_at` / `read_slot_at` was given an index past the last slot
+    SlotOutOfRange { index: usize, capacity: usize },
+    // The ring was marked poisoned (its producer died) and nothing is left to read
+    Poisoned,
+    // The segment was created anew since this handle attached, so its layout and
+    // indices are someone else's
+    Stale,
+    // `request_shutdown` was called on the ring while this call would have waited
+    Shutdown,
+    // The flag given to a `_cancelable` call was set while it waited
+    Cancelled,
+    // The producer overwrote `skipped` messages this consumer hadn't read yet, which
+    // are gone; the read index has been moved up to the oldest message still there
+    Lapped { skipped: u64 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Os(what, err) => write!(f, "{}: {}", what, err),
+            Error::OutOfSpace(what) => write!(f, "{}: no space left for shared memory", what),
+            Error::PermissionDenied(what) => write!(f, "{}: permission denied", what),
+            Error::NotFound(what) => write!(f, "{}: no such segment", what),
+            Error::TimedOut => write!(f, "Timed out waiting for shared memory"),
+            Error::Corrupt(what) => write!(f, "Corrupted shared memory: {}", what),
+            Error::MessageTooLarge { len, max } => {
+                write!(f, "Message of {} bytes exceeds the {} byte slot payload", len, max)
+            }
+            Error::InvalidConfig(what) => write!(f, "Invalid ring configuration: {}", what),
+            Error::Full => write!(f, "Ring buffer is full"),
+            Error::InvalidName(why) => write!(f, "Invalid shared memory name: {}", why),
+            Error::CursorOutOfRange { seq, oldest, next } => {
+                write!(f, "Read cursor {} outside the messages still in the ring ({}..={})", seq, oldest, next)
+            }
+            Error::WrongLength { len, expected } => {
+                write!(f, "Message of {} bytes on a ring of fixed {} byte messages", len, expected)
+            }
+            Error::SlotOutOfRange { index, capacity } => {
+                write!(f, "Slot {} out of range for a ring of {} slots", index, capacity)
+            }
+            Error::Poisoned => write!(f, "Ring buffer poisoned, its producer is gone"),
+            Error::Stale => write!(f, "Ring buffer was re-created since this handle attached"),
+            Error::Shutdown => write!(f, "Ring buffer is shutting down"),
+            Error::Cancelled => write!(f, "Ring buffer call cancelled"),
+            Error::Lapped { skipped } => write!(f, "Consumer lapped, {} unread messages overwritten", skipped),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    // `Error::Os` for a failed segment call, or the variant for its errno if it is
+    // one a caller can act on
+    fn os(what: &'static str, err: io::Error) -> Self {
+        match err.raw_os_error() {
+            Some(libc::ENOSPC) => Error::OutOfSpace(what),
+            Some(libc::EACCES | libc::EPERM) => Error::PermissionDenied(what),
+            Some(libc::ENOENT) => Error::NotFound(what),
+            _ => Error::Os(what, err),
+        }
+    }
+}
+
+// Longest name shm_open takes, not counting the leading slash
+#[cfg(target_os = "macos")]
+const MAX_NAME_LEN: usize = 30; // PSHMNAMLEN (31) counts the slash
+#[cfg(not(target_os = "macos"))]
+const MAX_NAME_LEN: usize = 255; // NAME_MAX for the file under /dev/shm
+
+// Segment name checked against the rules shm_open applies, so constructors can't
+// fail on it (or panic on an interior NUL) halfway through setting up a ring
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RingName(CString);
+
+impl TryFrom<&str> for RingName {
+    type Error = Error;
+
+    fn try_from(name: &str) -> Result<Self, Error> {
+        let Some(rest) = name.strip_prefix('/') else {
+            return Err(Error::InvalidName("must start with '/'"));
+        };
+        if rest.is_empty() {
+            return Err(Error::InvalidName("nothing after the leading '/'"));
+        }
+        if rest.contains('/') {
+            return Err(Error::InvalidName("'/' is only allowed as the first character"));
+        }
+        if rest.len() > MAX_NAME_LEN {
+            return Err(Error::InvalidName("longer than the platform allows"));
+        }
+        CString::new(name).map(RingName).map_err(|_| Error::InvalidName("contains a NUL byte"))
+    }
+}
+
+impl RingName {
+    // The name as given, leading slash included
+    fn as_str(&self) -> &str {
+        self.0.to_str().expect("RingName is built from a &str")
+    }
+}
+
+impl fmt::Display for RingName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.to_string_lossy())
+    }
+}
+
+// Byte order of the per-slot length prefix. The creator picks it and records it in
+// the header; attachers decode with whatever the header says. Header counters
+// themselves stay native-endian, so a host of the other byte order reads the
+// recorded value byte-swapped and `open` rejects the segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endianness {
+    Little = 1,
+    Big = 2, // Network order, for cross-architecture interop
+}
+
+impl Endianness {
+    fn from_header(raw: u32) -> Result<Self, Error> {
+        match raw {
+            1 => Ok(Endianness::Little),
+            2 => Ok(Endianness::Big),
+            _ => Err(Error::Corrupt("unknown length prefix byte order (header from a host of different endianness?)")),
+        }
+    }
+
+    #[inline(always)]
+    fn encode(self, len: u32) -> [u8; 4] {
+        match self {
+            Endianness::Little => len.to_le_bytes(),
+            Endianness::Big => len.to_be_bytes(),
+        }
+    }
+
+    #[inline(always)]
+    fn decode(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        }
+    }
+
+    #[inline(always)]
+    fn encode_u16(self, len: u16) -> [u8; 2] {
+        match self {
+            Endianness::Little => len.to_le_bytes(),
+            Endianness::Big => len.to_be_bytes(),
+        }
+    }
+
+    #[inline(always)]
+    fn decode_u16(self, bytes: [u8; 2]) -> u16 {
+        match self {
+            Endianness::Little => u16::from_le_bytes(bytes),
+            Endianness::Big => u16::from_be_bytes(bytes),
+        }
+    }
+}
+
+// Width of the length in front of each message of a `Framing::LengthPrefixed` ring
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum PrefixWidth {
+    U16, // Messages of at most 65534 bytes, two bytes more payload per slot
+    #[default]
+    U32,
+}
+
+// How a message sits in its slot. Recorded in the header like the byte order, so
+// readers and writers can't disagree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    LengthPrefixed(PrefixWidth), // Length (see `Endianness`), then the message
+    Fixed(usize),                // Every message is exactly this long and stored without a prefix
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Framing::LengthPrefixed(PrefixWidth::U32)
+    }
+}
+
+impl Framing {
+    // 0 and `usize::MAX` stand for a 4- and a 2-byte `LengthPrefixed`, lengths
+    // `Fixed` can't have
+    fn to_header(self) -> usize {
+        match self {
+            Framing::LengthPrefixed(PrefixWidth::U32) => 0,
+            Framing::LengthPrefixed(PrefixWidth::U16) => usize::MAX,
+            Framing::Fixed(len) => len,
+        }
+    }
+
+    fn from_header(raw: usize) -> Self {
+        match raw {
+            0 => Framing::LengthPrefixed(PrefixWidth::U32),
+            usize::MAX => Framing::LengthPrefixed(PrefixWidth::U16),
+            len => Framing::Fixed(len),
+        }
+    }
+
+    // Bytes each slot spends in front of the message
+    #[inline(always)]
+    fn overhead(self) -> usize {
+        match self {
+            Framing::LengthPrefixed(PrefixWidth::U16) => 2,
+            Framing::LengthPrefixed(PrefixWidth::U32) => 4,
+            Framing::Fixed(_) => 0,
+        }
+    }
+
+    // Longest message a `slot_size`-byte slot holds. The all-ones prefix is kept
+    // for `tombstone`.
+    fn max_message_len(self, slot_size: usize) -> usize {
+        let room = slot_size - self.overhead();
+        match self {
+            Framing::LengthPrefixed(PrefixWidth::U16) => room.min(u16::MAX as usize - 1),
+            Framing::LengthPrefixed(PrefixWidth::U32) => room.min(u32::MAX as usize - 1),
+            Framing::Fixed(_) => room,
+        }
+    }
+
+    // The length that marks a slot abandoned by its producer (see
+    // `SharedRingBuffer::abandon_slot`), none for `Fixed`, which has no prefix
+    fn tombstone(self) -> Option<usize> {
+        match self {
+            Framing::LengthPrefixed(PrefixWidth::U16) => Some(u16::MAX as usize),
+            Framing::LengthPrefixed(PrefixWidth::U32) => Some(u32::MAX as usize),
+            Framing::Fixed(_) => None,
+        }
+    }
+
+    // Where the message in `slot` starts and how long it says it is, unchecked
+    #[inline(always)]
+    unsafe fn read_len(self, slot: *const u8, endianness: Endianness) -> (usize, usize) {
+        match self {
+            Framing::LengthPrefixed(PrefixWidth::U16) => {
+                let mut len_bytes = [0u8; 2];
+                ptr::copy_nonoverlapping(slot, len_bytes.as_mut_ptr(), 2);
+                (2, endianness.decode_u16(len_bytes) as usize)
+            }
+            Framing::LengthPrefixed(PrefixWidth::U32) => {
+                let mut len_bytes = [0u8; 4];
+                ptr::copy_nonoverlapping(slot, len_bytes.as_mut_ptr(), 4);
+                (4, endianness.decode(len_bytes) as usize)
+            }
+            Framing::Fixed(len) => (0, len),
+        }
+    }
+
+    // Record the length of the message in `slot`, which must fit the prefix
+    #[inline(always)]
+    unsafe fn write_len(self, slot: *mut u8, len: usize, endianness: Endianness) {
+        match self {
+            Framing::LengthPrefixed(PrefixWidth::U16) => {
+                ptr::copy_nonoverlapping(endianness.encode_u16(len as u16).as_ptr(), slot, 2)
+            }
+            Framing::LengthPrefixed(PrefixWidth::U32) => {
+                ptr::copy_nonoverlapping(endianness.encode(len as u32).as_ptr(), slot, 4)
+            }
+            Framing::Fixed(_) => {}
+        }
+    }
+
+    fn check(self, slot_size: usize) -> Result<(), Error> {
+        match self {
+            Framing::Fixed(len) if len == 0 || len > slot_size => {
+                Err(Error::InvalidConfig("fixed message length must be between 1 and slot_size"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Per-message metadata written between a slot's length prefix and the message by
+// `write_message_framed`, and checked and decoded by `read_message_framed`. The
+// layout is the caller's choice per message type, pick it with the type
+// parameter; the ring doesn't record it, so both sides must agree, as with
+// `write_message_typed`. `LenOnly`, `LenCrc`, `LenMagicCrc` and `LenTimestampTag` are built
+// in.
+trait SlotHeader {
+    // Bytes the header takes in front of the message, at most `SLOT_HEADER_MAX`
+    const SIZE: usize;
+
+    // Encode the header for `message` into `header` (`SIZE` bytes)
+    fn write(header: &mut [u8], message: &[u8], tag: u32);
+
+    // Decode (and check) the header in front of `message`
+    fn read(header: &[u8], message: &[u8]) -> Result<Frame, Error>;
+}
+
+// What a `SlotHeader` recorded next to a message; fields its layout doesn't carry
+// are 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Frame {
+    len: usize,     // Of the message, without the header
+    timestamp: u64, // When it was written, in nanoseconds since the Unix epoch
+    tag: u32,
+}
+
+// Nothing beyond the ring's own length prefix
+struct LenOnly;
+
+impl SlotHeader for LenOnly {
+    const SIZE: usize = 0;
+
+    fn write(_header: &mut [u8], _message: &[u8], _tag: u32) {}
+
+    fn read(_header: &[u8], message: &[u8]) -> Result<Frame, Error> {
+        Ok(Frame { len: message.len(), ..Frame::default() })
+    }
+}
+
+// A CRC-32 (IEEE) of the message, little-endian, checked on read
+struct LenCrc;
+
+impl SlotHeader for LenCrc {
+    const SIZE: usize = 4;
+
+    fn write(header: &mut [u8], message: &[u8], _tag: u32) {
+        header.copy_from_slice(&crc32(message).to_le_bytes());
+    }
+
+    fn read(header: &[u8], message: &[u8]) -> Result<Frame, Error> {
+        if u32::from_le_bytes(header.try_into().unwrap()) != crc32(message) {
+            return Err(Error::Corrupt("message doesn't match its CRC"));
+        }
+        Ok(Frame { len: message.len(), ..Frame::default() })
+    }
+}
+
+// `LenMagicCrc::MAGIC` followed by a CRC-32 of the message, checked on read, so a
+// consumer with `RingConfig::resync_on_corruption` can tell a message from garbage
+// when it skips ahead past a corrupt slot
+struct LenMagicCrc;
+
+impl LenMagicCrc {
+    const MAGIC: [u8; 4] = *b"SHM\x01";
+}
+
+impl SlotHeader for LenMagicCrc {
+    const SIZE: usize = 8;
+
+    fn write(header: &mut [u8], message: &[u8], _tag: u32) {
+        header[..4].copy_from_slice(&Self::MAGIC);
+        header[4..].copy_from_slice(&crc32(message).to_le_bytes());
+    }
+
+    fn read(header: &[u8], message: &[u8]) -> Result<Frame, Error> {
+        if header[..4] != Self::MAGIC {
+            return Err(Error::Corrupt("slot header doesn't start with the magic"));
+        }
+        if u32::from_le_bytes(header[4..].try_into().unwrap()) != crc32(message) {
+            return Err(Error::Corrupt("message doesn't match its CRC"));
+        }
+        Ok(Frame { len: message.len(), ..Frame::default() })
+    }
+}
+
+// The write time (`SystemTime`, so comparable across processes) and the caller's
+// tag, both little-endian
+struct LenTimestampTag;
+
+impl SlotHeader for LenTimestampTag {
+    const SIZE: usize = 12;
+
+    fn write(header: &mut [u8], _message: &[u8], tag: u32) {
+        let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |t| t.as_nanos() as u64);
+        header[..8].copy_from_slice(&timestamp.to_le_bytes());
+        header[8..].copy_from_slice(&tag.to_le_bytes());
+    }
+
+    fn read(header: &[u8], message: &[u8]) -> Result<Frame, Error> {
+        Ok(Frame {
+            len: message.len(),
+            timestamp: u64::from_le_bytes(header[..8].try_into().unwrap()),
+            tag: u32::from_le_bytes(header[8..].try_into().unwrap()),
+        })
+    }
+}
+
+// CRC-32 with the IEEE polynomial (as zlib and Ethernet), for `LenCrc` and `LenMagicCrc`
+fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    !bytes.iter().fold(!0u32, |crc, &byte| (crc >> 8) ^ TABLE[((crc ^ byte as u32) & 0xFF) as usize])
+}
+
+// `T` on a cache line of its own, so the producer's index and the consumer's don't
+// share one (false sharing). The line size is picked per `target_arch`: 128 bytes
+// where cores fetch or invalidate in pairs of 64-byte lines (x86_64's adjacent-line
+// prefetch), or use 128-byte lines outright (Apple Silicon and other aarch64, POWER),
+// 64 elsewhere. `CACHE_LINE` is the size in use.
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "powerpc64"), repr(align(128)))]
+#[cfg_attr(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "powerpc64")), repr(align(64)))]
+struct CachePadded<T>(T);
+
+impl<T> std::ops::Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+// Lives at the start of the mapping so every attached process sees the same indices
+#[repr(C)]
+struct Header {
+    initialized: AtomicU32, // Stored last (Release) by the creator
+    endianness: AtomicU32,  // `Endianness` of the length prefix
+    zero_on_recycle: AtomicU32, // Nonzero if readers clear each slot they consume
+    producer_done: AtomicU32,   // Set by `mark_done` after the producer's last message
+    poisoned: AtomicU32,        // Set by `poison` once the producer is known dead
+    shutdown: AtomicU32,        // Set by `request_shutdown`
+    user_len: AtomicUsize,      // Bytes reserved after the header, see `RingConfig::user_header`
+    write_idx: CachePadded<AtomicU64>, // Byte counters, 64 bits even on 32-bit targets, see `is_full`
+    read_idx: CachePadded<AtomicU64>,  // On a line of its own, away from the producer's
+    overflow_count: AtomicUsize, // Writes rejected for lack of slot space
+    stall_count: AtomicUsize,    // Writes that found the ring full and waited for a slot
+    buffer_size: AtomicUsize,    // Layout chosen by the creator
+    slot_size: AtomicUsize,
+    framing: AtomicUsize, // `Framing::to_header`
+    acked: AtomicU64,     // Messages acknowledged with `ack`, counted from the first
+    epoch: AtomicU64,     // Picked anew by every `create`, see `Error::Stale`
+    written_base: AtomicU64, // Messages written before the last `reset_stats`
+    read_base: AtomicU64,    // Messages read before the last `reset_stats`
+    heartbeat: AtomicU64,    // `monotonic_nanos` at the last `write_heartbeat`, 0 for none yet
+    max_occupancy: AtomicU64, // Most messages ever unread at once, see `high_water_mark`
+}
+
+const _: () = assert!(HEADER_SIZE <= CONTROL_SIZE, "Header must fit in the control segment");
+const _: () = assert!(
+    std::mem::offset_of!(Header, write_idx).is_multiple_of(8) && std::mem::offset_of!(Header, read_idx).is_multiple_of(8)
+);
+
+// Snapshot of the ring counters, see `SharedRingBuffer::stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stats {
+    written: u64,     // Messages published since the segment was created or the stats reset
+    read: u64,        // Messages consumed since the segment was created or the stats reset
+    overflows: usize, // Messages dropped because they didn't fit
+    stalls: usize,    // Writes that had to wait for a free slot
+}
+
+// Which of the optional page setup asked of `RingConfig` this handle actually got,
+// see `SharedRingBuffer::effective_config`. Options that can't be had on a host
+// are recorded here rather than failing `build`/`open`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct EffectiveConfig {
+    populated: bool,  // Mapped with `MAP_POPULATE`, only where the target has it
+    prefaulted: bool, // Every page touched after mapping, see `SharedRingBuffer::prefault`
+}
+
+// How much of a handle's mapping is in RAM, see `SharedRingBuffer::residency`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ResidencyReport {
+    pages: usize,    // Pages of the header and data region mapped by this handle
+    resident: usize, // Of those, resident when `residency` was called
+}
+
+impl ResidencyReport {
+    // Every page resident, which a ring `lock_pages`d or prefaulted should stay
+    fn fully_resident(&self) -> bool {
+        self.resident == self.pages
+    }
+}
+
+// One slot as reported by `SharedRingBuffer::scan`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SlotStatus {
+    slot: usize,        // Position in the data region, from 0
+    unread: bool,       // Between `read_idx` and `write_idx`, i.e. still to be consumed
+    len: Option<usize>, // Message length per the framing, `None` if it overruns the slot
+}
+
+// How `write_message`/`read_message` wait on a full/empty ring. The budgeted
+// strategies spin until `max_spin_budget` has gone by in the same call and then
+// back off between checks, so a stalled counterpart can't keep this thread
+// burning a core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum WaitStrategy {
+    #[default]
+    Spin,                                                       // Spin for as long as it takes
+    SpinThenYield { max_spin_budget: Duration },                // Then yield the core between checks
+    SpinThenPark { max_spin_budget: Duration, park: Duration }, // Then sleep up to `park` between checks
+    Monitor, // Like `Spin`, but napping the core until the awaited index changes, see `monitor_wait`
+}
+
+impl WaitStrategy {
+    // One wait step of a call that started waiting at `since`, for a change to
+    // `watch` if it is waiting on an index rather than the clock. Returning says
+    // nothing about the ring: a step can end early or for no reason at all (a spurious
+    // `park_timeout` wakeup, an `unpark` meant for something else, a monitor nap cut
+    // short, an interrupted wait), so every caller re-checks its own condition after
+    // each step and waits again if it doesn't hold yet. A wakeup backend has to keep
+    // that contract rather than report readiness.
+    #[inline(always)]
+    fn wait(&self, since: Instant, step: Duration, watch: Option<&AtomicU64>) {
+        match *self {
+            WaitStrategy::SpinThenYield { max_spin_budget } if since.elapsed() >= max_spin_budget => {
+                thread::yield_now()
+            }
+            WaitStrategy::SpinThenPark { max_spin_budget, park } if since.elapsed() >= max_spin_budget => {
+                thread::park_timeout(park)
+            }
+            WaitStrategy::Monitor => monitor_wait(watch, step),
+            _ => spin_wait(step),
+        }
+    }
+}
+
+// What `write_message` does when the ring is full, as decided by a `FullPolicy::Hook`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FullAction {
+    Retry,     // Wait a moment, then check again (and call the hook again if still full)
+    Drop,      // Give up on this message, `write_message` returns `Error::Full`
+    Overwrite, // Discard the oldest unread message to make room
+}
+
+// What `write_message` does when it finds the ring full
+#[derive(Default)]
+enum FullPolicy {
+    #[default]
+    Block,     // Wait for the consumer to free a slot
+    Drop,      // Give up on the message, `write_message` returns `Error::Full`
+    Overwrite, // Discard the oldest unread message to make room (not on zero-on-recycle rings)
+    Hook(Box<dyn Fn() -> FullAction + Send + Sync>), // Ask the hook, every time the ring is found full
+}
+
+impl FullPolicy {
+    #[inline(always)]
+    fn action(&self) -> FullAction {
+        match self {
+            FullPolicy::Block => FullAction::Retry,
+            FullPolicy::Drop => FullAction::Drop,
+            FullPolicy::Overwrite => FullAction::Overwrite,
+            FullPolicy::Hook(on_full) => on_full(),
+        }
+    }
+}
+
+// Producer pacing for `RingConfig::rate_limit`: a token bucket refilled with
+// `per_sec` messages a second and holding up to `burst` of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RatePerSec {
+    per_sec: u32,
+    burst: u32,
+}
+
+// `RatePerSec` as a generic cell rate algorithm: the bucket is kept as the time
+// the next message is due, one atomic that writers sharing a handle update by CAS
+struct RateLimiter {
+    interval: u64,  // Nanoseconds per message
+    tolerance: u64, // How far ahead of schedule a burst may run, in nanoseconds
+    start: Instant,
+    due: AtomicU64, // When the next message is due, in nanoseconds since `start`
+}
+
+impl RateLimiter {
+    fn new(rate: RatePerSec) -> Self {
+        let interval = 1_000_000_000 / u64::from(rate.per_sec.max(1));
+        let tolerance = interval * u64::from(rate.burst.max(1) - 1);
+        Self { interval, tolerance, start: Instant::now(), due: AtomicU64::new(0) }
+    }
+
+    // Take a token, or say how long until there is one
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let now = self.start.elapsed().as_nanos() as u64;
+        let mut due = self.due.load(Ordering::Relaxed);
+        loop {
+            let earliest = due.saturating_sub(self.tolerance);
+            if now < earliest {
+                return Err(Duration::from_nanos(earliest - now));
+            }
+            match self.due.compare_exchange_weak(due, due.max(now) + self.interval, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => return Ok(()),
+                Err(actual) => due = actual,
+            }
+        }
+    }
+}
+
+// Every tunable of a ring in one place, e.g.
+//
+//     RingConfig::new(&name).slot_size(256).capacity(4096).full(FullPolicy::Drop).build()
+//
+// `build` creates the ring with the given layout (sizes, byte order, zeroing, split
+// segments). `open` attaches to an existing one and takes the layout from its header
+// instead, so only the per-handle settings (wait strategy, full policy, spin steps)
+// apply there. Anything left unset behaves as `SharedRingBuffer::new`/`open`.
+struct RingConfig {
+    name: RingName,
+    data_name: Option<RingName>,
+    file: Option<PathBuf>, // See `backing_file`
+    slot_size: usize,
+    capacity: usize, // In slots
+    endianness: Endianness,
+    framing: Framing,
+    zero_on_recycle: bool,
+    wait: WaitStrategy,
+    full: FullPolicy,
+    write_spin: Duration,
+    read_spin: Duration,
+    prefault: bool,
+    populate: bool,
+    exclusive: bool,
+    rate_limit: Option<RatePerSec>,
+    soft_full_reserve: usize, // Slots
+    unchecked: bool,
+    skip_lapped: bool,
+    resync_on_corruption: bool,
+    base_address: Option<usize>,
+    user_header: usize, // Bytes reserved after the header
+}
+
+impl RingConfig {
+    fn new(name: &RingName) -> Self {
+        Self {
+            name: name.clone(),
+            data_name: None,
+            file: None,
+            slot_size: SLOT_SIZE,
+            capacity: BUFFER_SIZE / SLOT_SIZE,
+            endianness: Endianness::Little,
+            framing: Framing::default(),
+            zero_on_recycle: false,
+            wait: WaitStrategy::Spin,
+            full: FullPolicy::Block,
+            write_spin: WRITE_SPIN,
+            read_spin: READ_SPIN,
+            prefault: false,
+            populate: false,
+            exclusive: false,
+            rate_limit: None,
+            soft_full_reserve: 0,
+            unchecked: false,
+            skip_lapped: false,
+            resync_on_corruption: false,
+            base_address: None,
+            user_header: 0,
+        }
+    }
+
+    // Bytes per slot, the length prefix of `Framing::LengthPrefixed` included
+    fn slot_size(mut self, slot_size: usize) -> Self {
+        self.slot_size = slot_size;
+        self
+    }
+
+    // Number of slots, at least 2
+    fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    // Byte order of the length prefix
+    fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    // How messages are stored in their slots, e.g. `Framing::Fixed(64)` for a stream
+    // of 64-byte records that doesn't need a length prefix per message
+    fn framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    // Length prefix width, `framing(Framing::LengthPrefixed(width))`: `PrefixWidth::U16`
+    // gives small slots two more payload bytes but caps messages at 65534 bytes
+    fn prefix_width(self, width: PrefixWidth) -> Self {
+        self.framing(Framing::LengthPrefixed(width))
+    }
+
+    // Have readers zero each slot once the message has been copied out, so payloads
+    // don't linger in the mapping, at the cost of a slot-sized memset per read. The
+    // whole slot is cleared whatever the message length, so the extra time doesn't
+    // give the size away; a message is still visible to every process mapping the
+    // segment while it is queued, and the reader's own copy isn't covered.
+    fn zero_on_recycle(mut self, zero_on_recycle: bool) -> Self {
+        self.zero_on_recycle = zero_on_recycle;
+        self
+    }
+
+    // Keep only the header in `name` and give the slots a segment of their own,
+    // `data_name`, so processes polling the indices never touch the data pages.
+    // Attachers must pass the same `data_name`.
+    fn data_segment(mut self, data_name: &RingName) -> Self {
+        self.data_name = Some(data_name.clone());
+        self
+    }
+
+    // Keep the ring in the file at `path` (e.g. on a local or SMB share) instead of
+    // shared memory; `name` then only labels it. The file holds exactly what
+    // the segment would, header and slots, so a Windows process can attach to a ring a
+    // POSIX one created and the other way round, given the same byte order, word
+    // size and `DATA_OFFSET` (the Windows `magic-ring` build moves the data region).
+    // The file outlives the ring. Not with `data_segment`.
+    fn backing_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    // How this handle waits on a full or empty ring
+    fn wait(mut self, wait: WaitStrategy) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    // What this handle's `write_message` does on a full ring
+    fn full(mut self, full: FullPolicy) -> Self {
+        self.full = full;
+        self
+    }
+
+    // How long one wait step spins on a full ring (`write`) and an empty one (`read`)
+    fn spin_steps(mut self, write: Duration, read: Duration) -> Self {
+        self.write_spin = write;
+        self.read_spin = read;
+        self
+    }
+
+    // `SharedRingBuffer::prefault` the handle before returning it
+    fn prefault(mut self, prefault: bool) -> Self {
+        self.prefault = prefault;
+        self
+    }
+
+    // Populate the page tables when mapping (`MAP_POPULATE`, on Linux) instead of
+    // faulting pages in on first touch, for this handle only. Where there's no
+    // `MAP_POPULATE` the handle is prefaulted instead. Costs the faults up front, see
+    // `SharedRingBuffer::prefault`; the default stays lazy.
+    fn populate(mut self, populate: bool) -> Self {
+        self.populate = populate;
+        self
+    }
+
+    // Have `build` fail if a segment called `name` already exists (`O_EXCL`) instead
+    // of taking it over; without this an existing segment is resized to the new
+    // layout and its header re-initialized, unless its creator is still alive (see
+    // `create_segment`).
+    fn exclusive(mut self, exclusive: bool) -> Self {
+        self.exclusive = exclusive;
+        self
+    }
+
+    // Pace this handle's writes to `rate` (`None`, the default, doesn't), so a bursty
+    // producer spreads its messages out instead of hammering a full ring. A write
+    // that is ahead of schedule sleeps most of the gap and waits out the rest with
+    // the `WaitStrategy`, before any wait for a free slot. `try_write_message`
+    // never waits, so it isn't paced.
+    fn rate_limit(mut self, rate: Option<RatePerSec>) -> Self {
+        self.rate_limit = rate;
+        self
+    }
+
+    // Have this handle's writes treat the ring as full once only `slots` are free
+    // (a soft full at `capacity - slots`, 0 by default), so under bursty load the
+    // producer waits while the consumer still has slack to catch up, instead of the
+    // ring swinging from completely full to empty. It moves every full decision
+    // (`is_full`, `writable`, the wait or `FullPolicy` in `write_message`), never the
+    // consumer's, which still drains every slot. Less than the capacity.
+    fn soft_full_reserve(mut self, slots: usize) -> Self {
+        self.soft_full_reserve = slots;
+        self
+    }
+
+    // Skip the checks on this handle's hot path for the last few nanoseconds: the
+    // message size and `Framing::Fixed` length checks and the `Error::Stale` check
+    // in `write_message`, and the slot bounds and length prefix checks in
+    // `read_message`, each left as one branch on this flag. Pick `LenOnly` over
+    // `LenCrc` to drop the CRC as well. Benchmarks and validated deployments only.
+    //
+    // # Safety
+    //
+    // Every message written must fit the slot (`max_message_len`, and the fixed
+    // length for `Framing::Fixed`), and neither the segment nor its indices may be
+    // corrupted or re-created under the handle; otherwise a read or write runs
+    // past its slot, which is undefined behaviour.
+    unsafe fn unchecked(mut self) -> Self {
+        self.unchecked = true;
+        self
+    }
+
+    // Carry on with the oldest message still in the ring when the producer has lapped
+    // this consumer (overwritten slots it hadn't read), instead of `read_message`
+    // returning `Error::Lapped` once with the number of messages lost. Either way the
+    // lost messages count towards `overflows` in `stats`.
+    fn skip_lapped(mut self, skip: bool) -> Self {
+        self.skip_lapped = skip;
+        self
+    }
+
+    // Have this handle's consuming reads step over a slot they find corrupt (a length
+    // past the slot, a read index off a slot boundary, or for `read_message_framed` a
+    // header that doesn't check out) to the next slot boundary instead of returning
+    // `Error::Corrupt`, so a long-lived consumer rides out a scribbled slot. The bytes
+    // skipped add up in `resynced_bytes`. Pair it with `LenMagicCrc` framing, whose
+    // magic and CRC make it unlikely that garbage passes for a message: a plain
+    // message whose scribbled length happens to fit is still returned.
+    fn resync_on_corruption(mut self, resync: bool) -> Self {
+        self.resync_on_corruption = resync;
+        self
+    }
+
+    // Map the segment at `base` (nonzero, page aligned) instead of wherever the kernel
+    // likes, so every process that asks for the same base sees the ring at the same
+    // virtual address and absolute pointers into it mean the same thing in all of them.
+    // Linux maps it with `MAP_FIXED_NOREPLACE`; elsewhere the base is only a hint that is
+    // checked afterwards. Either way nothing already mapped there is ever replaced: if
+    // ASLR has put a library, heap or stack at that address in some process, its `build`
+    // or `attach` fails with `EEXIST` rather than landing elsewhere, so pick an address
+    // high in the user range that none of the programs use. Pointers only agree between
+    // builds with the same `DATA_OFFSET` and `magic-ring` setting. Not for split rings.
+    fn base_address(mut self, base: Option<usize>) -> Self {
+        self.base_address = base;
+        self
+    }
+
+    // Reserve `len` bytes (at most `USER_HEADER_MAX`) right after the header for the
+    // application's own shared state, e.g. a schema version or the producer's pid,
+    // see `SharedRingBuffer::user_header`. The area comes out of the padding before
+    // the data region, so the data layout doesn't change.
+    fn user_header(mut self, len: usize) -> Self {
+        self.user_header = len;
+        self
+    }
+
+    // Create the ring and initialize its header
+    fn build(self) -> Result<SharedRingBuffer, Error> {
+        let buffer_size = self
+            .capacity
+            .checked_mul(self.slot_size)
+            .ok_or(Error::InvalidConfig("capacity * slot_size overflows"))?;
+        let ring = SharedRingBuffer::create(&self, buffer_size)?;
+        self.apply(ring)
+    }
+
+    // Attach to a ring created by another process, waiting up to `timeout` for its
+    // creator to initialize the header
+    fn open(self, timeout: Duration) -> Result<SharedRingBuffer, Error> {
+        let ring = self.attach(timeout)?;
+        self.apply(ring)
+    }
+
+    // `open` for a ring that may not exist yet, e.g. in a consumer started before its
+    // producer: while there's no ring of that name it tries again every `interval`
+    // instead of failing. `timeout` bounds the whole wait, the creator's header
+    // initialization included, so the ring returned is always ready to use.
+    fn open_retry(self, timeout: Duration, interval: Duration) -> Result<SharedRingBuffer, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.attach(deadline.saturating_duration_since(Instant::now())) {
+                Ok(ring) => return self.apply(ring),
+                Err(Error::NotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::TimedOut);
+            }
+            thread::sleep(interval.min(remaining));
+        }
+    }
+
+    // `open_single` or `open_split`, whichever `data_segment` and `backing_file` call for
+    fn attach(&self, timeout: Duration) -> Result<SharedRingBuffer, Error> {
+        self.check_base_address()?;
+        let (flags, base) = (if self.populate { POPULATE_FLAGS } else { 0 }, self.base_address);
+        match (&self.file, &self.data_name) {
+            (None, None) => {
+                SharedRingBuffer::open_single(open_segment(&self.name, O_RDWR)?, &self.name, timeout, flags, base)
+            }
+            (Some(path), None) => SharedRingBuffer::open_single(open_file(path)?, &self.name, timeout, flags, base),
+            (None, Some(data_name)) => SharedRingBuffer::open_split(&self.name, data_name, timeout, flags),
+            (Some(_), Some(_)) => Err(Error::InvalidConfig("a file-backed ring can't have a data segment")),
+        }
+    }
+
+    // `base_address` is one `map` can honour
+    fn check_base_address(&self) -> Result<(), Error> {
+        match self.base_address {
+            Some(_) if self.data_name.is_some() => Err(Error::InvalidConfig("a split ring can't have a base address")),
+            Some(base) if base == 0 || !base.is_multiple_of(PAGE_SIZE) => {
+                Err(Error::InvalidConfig("base_address must be nonzero and page aligned"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(self, mut ring: SharedRingBuffer) -> Result<SharedRingBuffer, Error> {
+        if self.soft_full_reserve >= ring.capacity() {
+            return Err(Error::InvalidConfig("soft_full_reserve must leave at least one slot"));
+        }
+        ring.soft_full_reserve = (self.soft_full_reserve * ring.slot_size) as u64;
+        ring.unchecked = self.unchecked;
+        ring.skip_lapped = self.skip_lapped;
+        ring.resync_on_corruption = self.resync_on_corruption;
+        ring.wait = self.wait;
+        ring.full = self.full;
+        ring.write_spin = self.write_spin;
+        ring.read_spin = self.read_spin;
+        ring.rate_limit = self.rate_limit.map(RateLimiter::new);
+        ring.effective.populated = self.populate && POPULATE_FLAGS != 0;
+        if self.prefault || (self.populate && !ring.effective.populated) {
+            ring.prefault();
+            ring.effective.prefaulted = true;
+        }
+        Ok(ring)
+    }
+}
+
+// Ordering: messages are read in exactly the order they were published, none
+// skipped. A message is published by the single `write_idx` store that follows its
+// copy (Release), and a reader's Acquire load of `write_idx` makes it and every
+// earlier message visible, so seeing message N means seeing all of 0..N. This needs
+// one producer at a time per ring: `write_message` is a load/copy/store, not a
+// claim, so concurrent producers would race for the same slot rather than publish
+// out of order. In-process fan-in goes through `channel`, whose senders take turns.
+// Nothing is flushed anywhere: the ring lives as long as its segment does.
+struct SharedRingBuffer {
+    name: RingName,
+    header: *mut Header,
+    buffer: *mut u8,
+    buffer_size: usize, // Length of the data region
+    slot_size: usize,
+    owner: bool,        // Created the segment, so unlinks it on drop
+    endianness: Endianness,
+    framing: Framing,
+    zero_on_recycle: bool,
+    full: FullPolicy,
+    data_segment: Option<CString>, // Set if the slots live apart from the header, see `RingConfig::data_segment`
+    file_backed: bool,              // Lives in a file, which isn't unlinked on drop, see `RingConfig::backing_file`
+    wait: WaitStrategy,
+    write_spin: Duration,
+    read_spin: Duration,
+    epoch: u64, // `Header::epoch` when this handle attached
+    effective: EffectiveConfig,
+    table_layout: Result<(), &'static str>, // Checked when built or opened, see `table_layout`
+    rate_limit: Option<RateLimiter>,
+    soft_full_reserve: u64, // `RingConfig::soft_full_reserve`, in bytes
+    unchecked: bool,        // See `RingConfig::unchecked`
+    skip_lapped: bool,      // See `RingConfig::skip_lapped`
+    resync_on_corruption: bool, // See `RingConfig::resync_on_corruption`
+    resynced: AtomicU64,        // Bytes skipped past corruption, see `resynced_bytes`
+    held: AtomicU64, // Slot lent out by `read_message_cow`, freed by the next read
+    owner_locks: Vec<OwnedFd>, // The creator's `flock`s, see `create_segment`
+    fd: OwnedFd,               // Of the segment holding the header, see `raw_fd`
+    closed: bool,              // Torn down by `close`, so `Drop` has nothing left to do
+}
+
+unsafe impl Send for SharedRingBuffer {}
+unsafe impl Sync for SharedRingBuffer {}
+
+impl SharedRingBuffer {
+    // Create the segment and initialize its header, see `RingConfig` for the tunables
+    fn new(name: &RingName) -> Result<Self, Error> {
+        RingConfig::new(name).build()
+    }
+
+    // Bytes of shared memory `build` would create for `config`, to check against the
+    // free space in `/dev/shm` (or the file system holding `backing_file`) before
+    // committing to it: the header padded to `DATA_OFFSET`, or `CONTROL_SIZE` for a
+    // split ring, plus the data region, all of it sized up front. `usize::MAX` if
+    // `capacity * slot_size` overflows, which `build` rejects.
+    fn required_size(config: &RingConfig) -> usize {
+        let header = if config.data_name.is_some() { CONTROL_SIZE } else { DATA_OFFSET };
+        config.capacity.checked_mul(config.slot_size).and_then(|data| data.checked_add(header)).unwrap_or(usize::MAX)
+    }
+
+    fn create(config: &RingConfig, buffer_size: usize) -> Result<Self, Error> {
+        let (name, slot_size, endianness, zero_on_recycle) =
+            (&config.name, config.slot_size, config.endianness, config.zero_on_recycle);
+        check_layout(buffer_size, slot_size)?;
+        config.framing.check(slot_size)?;
+        if config.user_header > USER_HEADER_MAX {
+            return Err(Error::InvalidConfig("user_header is larger than USER_HEADER_MAX"));
+        }
+
+        let flags = if config.populate { POPULATE_FLAGS } else { 0 };
+        let mut owner_locks = Vec::new();
+        if config.file.is_some() && config.data_name.is_some() {
+            return Err(Error::InvalidConfig("a file-backed ring can't have a data segment"));
+        }
+        config.check_base_address()?;
+        let mut ring = match &config.data_name {
+            None => {
+                let (fd, lock) = match &config.file {
+                    Some(path) => create_file(path, DATA_OFFSET + buffer_size, config.exclusive)?,
+                    None => create_segment(name, DATA_OFFSET + buffer_size, config.exclusive)?,
+                };
+                owner_locks.extend(lock);
+                let mut ring = Self::map(fd, name, buffer_size, true, flags, config.base_address)?;
+                ring.file_backed = config.file.is_some();
+                ring
+            }
+            Some(data_name) => {
+                // Data first, so an attacher finding the control segment finds both
+                let (data_fd, data_lock) = create_segment(data_name, buffer_size, config.exclusive)?;
+                let (control_fd, control_lock) =
+                    create_segment(name, CONTROL_SIZE, config.exclusive).inspect_err(|_| unsafe {
+                        close(data_fd);
+                        shm_unlink(data_name.0.as_ptr());
+                    })?;
+                owner_locks.extend(data_lock.into_iter().chain(control_lock));
+                Self::map_split(control_fd, data_fd, name, data_name, buffer_size, true, flags)
+                    .inspect_err(|_| unsafe {
+                        shm_unlink(data_name.0.as_ptr());
+                        shm_unlink(name.0.as_ptr());
+                    })?
+            }
+        };
+        ring.owner_locks = owner_locks;
+        ring.slot_size = slot_size;
+        ring.endianness = endianness;
+        ring.framing = config.framing;
+        ring.table_layout = table_layout(slot_size, config.framing);
+        ring.zero_on_recycle = zero_on_recycle;
+        ring.epoch = session_epoch();
+        let header = ring.header();
+        // A reused segment still has the old header published; withdraw it before
+        // rewriting anything, so attachers wait instead of reading a mix of both
+        header.initialized.store(0, Ordering::Relaxed);
+        std::sync::atomic::fence(Ordering::Release);
+        header.write_idx.store(0, Ordering::Relaxed);
+        header.read_idx.store(0, Ordering::Relaxed);
+        header.overflow_count.store(0, Ordering::Relaxed);
+        header.stall_count.store(0, Ordering::Relaxed);
+        header.producer_done.store(0, Ordering::Relaxed);
+        header.poisoned.store(0, Ordering::Relaxed);
+        header.shutdown.store(0, Ordering::Relaxed);
+        header.acked.store(0, Ordering::Relaxed);
+        header.written_base.store(0, Ordering::Relaxed);
+        header.read_base.store(0, Ordering::Relaxed);
+        header.heartbeat.store(0, Ordering::Relaxed);
+        header.max_occupancy.store(0, Ordering::Relaxed);
+        unsafe { ptr::write_bytes((ring.header as *mut u8).add(HEADER_SIZE), 0, config.user_header) };
+        header.user_len.store(config.user_header, Ordering::Relaxed);
+        header.epoch.store(ring.epoch, Ordering::Relaxed);
+        header.endianness.store(endianness as u32, Ordering::Relaxed);
+        header.zero_on_recycle.store(zero_on_recycle as u32, Ordering::Relaxed);
+        header.buffer_size.store(buffer_size, Ordering::Relaxed);
+        header.slot_size.store(slot_size, Ordering::Relaxed);
+        header.framing.store(config.framing.to_header(), Ordering::Relaxed);
+        // Publish the header last so attachers never see it half-written
+        header.initialized.store(INITIALIZED, Ordering::Release);
+        Ok(ring)
+    }
+
+    // Attach to a segment created by another process, waiting until its creator
+    // has finished initializing the header. The layout is taken from the header.
+    fn open(name: &RingName, timeout: Duration) -> Result<Self, Error> {
+        RingConfig::new(name).open(timeout)
+    }
+
+    // `open`, polling every `interval` until a segment called `name` exists, see
+    // `RingConfig::open_retry`
+    fn open_retry(name: &RingName, timeout: Duration, interval: Duration) -> Result<Self, Error> {
+        RingConfig::new(name).open_retry(timeout, interval)
+    }
+
+    fn open_single(
+        fd: libc::c_int,
+        name: &RingName,
+        timeout: Duration,
+        flags: libc::c_int,
+        base: Option<usize>,
+    ) -> Result<Self, Error> {
+        let deadline = Instant::now() + timeout;
+        let segment_size = wait_for_size(fd, deadline)?;
+        if segment_size <= DATA_OFFSET {
+            unsafe { close(fd) };
+            return Err(Error::InvalidConfig("segment has no data region, attach to split rings with RingConfig::data_segment"));
+        }
+
+        let mut ring = Self::map(fd, name, segment_size - DATA_OFFSET, false, flags, base)?;
+        ring.attach(deadline)?;
+        Ok(ring)
+    }
+
+    // `open_single` for a ring created with `RingConfig::data_segment`
+    fn open_split(name: &RingName, data_name: &RingName, timeout: Duration, flags: libc::c_int) -> Result<Self, Error> {
+        let deadline = Instant::now() + timeout;
+        let control_fd = open_segment(name, O_RDWR)?;
+        let close_control = |err: Error| {
+            unsafe { close(control_fd) };
+            err
+        };
+        if wait_for_size(control_fd, deadline)? != CONTROL_SIZE {
+            return Err(close_control(Error::InvalidConfig("not the control segment of a split ring")));
+        }
+        let data_fd = open_segment(data_name, O_RDWR).map_err(close_control)?;
+        let buffer_size = wait_for_size(data_fd, deadline).map_err(close_control)?;
+
+        let mut ring = Self::map_split(control_fd, data_fd, name, data_name, buffer_size, false, flags)?;
+        ring.attach(deadline)?;
+        Ok(ring)
+    }
+
+    // Wait for the creator to publish the header, then take the layout from it
+    fn attach(&mut self, deadline: Instant) -> Result<(), Error> {
+        while self.header().initialized.load(Ordering::Acquire) != INITIALIZED {
+            if Instant::now() >= deadline {
+                return Err(Error::TimedOut);
+            }
+            spin_wait(Duration::from_micros(100));
+        }
+        let header = self.header();
+        let endianness = Endianness::from_header(header.endianness.load(Ordering::Relaxed))?;
+        let slot_size = header.slot_size.load(Ordering::Relaxed);
+        let framing = Framing::from_header(header.framing.load(Ordering::Relaxed));
+        let zero_on_recycle = header.zero_on_recycle.load(Ordering::Relaxed) != 0;
+        let epoch = header.epoch.load(Ordering::Relaxed);
+        if header.buffer_size.load(Ordering::Relaxed) != self.buffer_size {
+            return Err(Error::Corrupt("segment size doesn't match the layout in its header"));
+        }
+        check_layout(self.buffer_size, slot_size)?;
+        framing.check(slot_size)?;
+        self.endianness = endianness;
+        self.framing = framing;
+        self.slot_size = slot_size;
+        self.table_layout = table_layout(slot_size, framing);
+        self.zero_on_recycle = zero_on_recycle;
+        self.epoch = epoch;
+        Ok(())
+    }
+
+    // Map the whole segment, with extra mmap `flags` and at `base` if given, taking
+    // ownership of `fd` (kept for `raw_fd`). The slot size and byte order are filled in
+    // by the caller.
+    fn map(
+        fd: libc::c_int,
+        name: &RingName,
+        buffer_size: usize,
+        owner: bool,
+        flags: libc::c_int,
+        base: Option<usize>,
+    ) -> Result<Self, Error> {
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let addr = unsafe { map_segment(fd.as_raw_fd(), DATA_OFFSET, buffer_size, flags, base) }
+            .map_err(|e| Error::os("Failed to map shared memory", e))?;
+
+        Ok(Self {
+            name: name.clone(),
+            header: addr as *mut Header,
+            buffer: unsafe { (addr as *mut u8).add(DATA_OFFSET) },
+            buffer_size,
+            slot_size: SLOT_SIZE,
+            owner,
+            endianness: Endianness::Little,
+            framing: Framing::default(),
+            zero_on_recycle: false,
+            full: FullPolicy::Block,
+            data_segment: None,
+            file_backed: false,
+            wait: WaitStrategy::Spin,
+            write_spin: WRITE_SPIN,
+            read_spin: READ_SPIN,
+            epoch: 0,
+            effective: EffectiveConfig::default(),
+            table_layout: Ok(()),
+            rate_limit: None,
+            soft_full_reserve: 0,
+            unchecked: false,
+            skip_lapped: false,
+            resync_on_corruption: false,
+            resynced: AtomicU64::new(0),
+            held: AtomicU64::new(NO_HELD_SLOT),
+            owner_locks: Vec::new(),
+            fd,
+            closed: false,
+        })
+    }
+
+    // `map` for a header and data region in separate segments, keeping `control_fd`
+    // and closing `data_fd`
+    fn map_split(
+        control_fd: libc::c_int,
+        data_fd: libc::c_int,
+        name: &RingName,
+        data_name: &RingName,
+        buffer_size: usize,
+        owner: bool,
+        flags: libc::c_int,
+    ) -> Result<Self, Error> {
+        let header = unsafe { mmap(ptr::null_mut(), CONTROL_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED | flags, control_fd, 0) };
+        let header = if header == libc::MAP_FAILED { Err(io::Error::last_os_error()) } else { Ok(header) };
+        let data = unsafe { map_segment(data_fd, 0, buffer_size, flags, None) };
+        let control_fd = unsafe { OwnedFd::from_raw_fd(control_fd) };
+        unsafe { close(data_fd) };
+
+        let (header, data) = match (header, data) {
+            (Ok(header), Ok(data)) => (header, data),
+            (Ok(header), Err(err)) => {
+                unsafe { munmap(header, CONTROL_SIZE) };
+                return Err(Error::os("Failed to map shared memory", err));
+            }
+            (Err(err), Ok(data)) => {
+                unsafe { munmap(data, Self::data_mapped_size(buffer_size)) };
+                return Err(Error::os("Failed to map shared memory", err));
+            }
+            (Err(err), Err(_)) => return Err(Error::os("Failed to map shared memory", err)),
+        };
+
+        Ok(Self {
+            name: name.clone(),
+            header: header as *mut Header,
+            buffer: data as *mut u8,
+            buffer_size,
+            slot_size: SLOT_SIZE,
+            owner,
+            endianness: Endianness::Little,
+            framing: Framing::default(),
+            zero_on_recycle: false,
+            full: FullPolicy::Block,
+            data_segment: Some(data_name.0.clone()),
+            file_backed: false,
+            wait: WaitStrategy::Spin,
+            write_spin: WRITE_SPIN,
+            read_spin: READ_SPIN,
+            epoch: 0,
+            effective: EffectiveConfig::default(),
+            table_layout: Ok(()),
+            rate_limit: None,
+            soft_full_reserve: 0,
+            unchecked: false,
+            skip_lapped: false,
+            resync_on_corruption: false,
+            resynced: AtomicU64::new(0),
+            held: AtomicU64::new(NO_HELD_SLOT),
+            owner_locks: Vec::new(),
+            fd: control_fd,
+            closed: false,
+        })
+    }
+
+    // Bytes mapped for a data region, mirror included
+    fn data_mapped_size(buffer_size: usize) -> usize {
+        if cfg!(feature = "magic-ring") {
+            2 * buffer_size
+        } else {
+            buffer_size
+        }
+    }
+
+    #[inline(always)]
+    fn header(&self) -> &Header {
+        unsafe { &*self.header }
+    }
+
+    // Offset in the data region of the slot at index `idx`
+    #[inline(always)]
+    fn slot_offset(&self, idx: u64) -> usize {
+        (idx % self.buffer_size as u64) as usize
+    }
+
+    // Debug builds only: `idx` must start a whole slot inside the data region, so a
+    // bad index panics under test instead of reading or writing past its slot.
+    // Release builds rely on the checks in `claim` and `read_slot`.
+    #[inline(always)]
+    fn debug_check_slot(&self, idx: u64) {
+        debug_assert!(idx.is_multiple_of(self.slot_size as u64), "index {} is not on a slot boundary", idx);
+        debug_assert!(self.slot_offset(idx) / self.slot_size < self.capacity(), "slot of index {} out of range", idx);
+        debug_assert!(
+            self.slot_offset(idx) + self.slot_size <= self.buffer_size,
+            "slot of index {} runs past the data region",
+            idx
+        );
+    }
+
+    // Touch every page of this handle's mapping (the mirror too, with `magic-ring`)
+    // so none of them faults on the hot path. Pages start out unmapped and fault in
+    // one by one on first access, which is what spikes early write/read latencies.
+    // The trade is one fault per 4 KiB up front, ~256 for the default 1 MB ring,
+    // and the whole ring resident from then on. Every handle has its own page
+    // tables, so producer and consumer each prefault. Touching is a no-op atomic
+    // write, harmless on a ring already in use.
+    fn prefault(&self) {
+        let touch = |base: *mut u8, len: usize| {
+            for offset in (0..len).step_by(PAGE_SIZE) {
+                unsafe { AtomicU8::from_ptr(base.add(offset)) }.fetch_or(0, Ordering::Relaxed);
+            }
+        };
+        touch(self.header as *mut u8, if self.data_segment.is_some() { CONTROL_SIZE } else { DATA_OFFSET });
+        touch(self.buffer, Self::data_mapped_size(self.buffer_size));
+    }
+
+    // Lock this handle's header and data region in RAM (`mlock`), faulting them in
+    // first, so the kernel can't reclaim them and the hot path never takes a major
+    // fault. Counts against `RLIMIT_MEMLOCK` (`ulimit -l`), past which it fails with
+    // `EPERM`/`ENOMEM`; the lock is this process's and ends when the ring is unmapped.
+    fn lock_pages(&self) -> Result<(), Error> {
+        for (base, len) in self.mapped_ranges() {
+            if unsafe { libc::mlock(base.cast(), len) } != 0 {
+                return Err(Error::os("Failed to lock shared memory", io::Error::last_os_error()));
+            }
+        }
+        Ok(())
+    }
+
+    // How many of this handle's header and data pages are resident (`mincore`), to
+    // check that `lock_pages` or `prefault` took and that the pages haven't been
+    // reclaimed since. The answer can be stale as soon as it's returned.
+    fn residency(&self) -> Result<ResidencyReport, Error> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let mut report = ResidencyReport { pages: 0, resident: 0 };
+        for (base, len) in self.mapped_ranges() {
+            let mut pages = vec![0u8; len.div_ceil(page_size)];
+            if unsafe { libc::mincore(base.cast(), len, pages.as_mut_ptr().cast()) } != 0 {
+                return Err(Error::os("Failed to query page residency", io::Error::last_os_error()));
+            }
+            report.pages += pages.len();
+            report.resident += pages.iter().filter(|&&page| page & 1 != 0).count();
+        }
+        Ok(report)
+    }
+
+    // The header (with the user area) and data region this handle maps, as
+    // (start, length), the latter without the `magic-ring` mirror
+    fn mapped_ranges(&self) -> [(*mut u8, usize); 2] {
+        let header_len = if self.data_segment.is_some() { CONTROL_SIZE } else { DATA_OFFSET };
+        [(self.header as *mut u8, header_len), (self.buffer, self.buffer_size)]
+    }
+
+    // The page setup this handle got from `RingConfig`, for logging what a host
+    // allowed
+    fn effective_config(&self) -> EffectiveConfig {
+        self.effective
+    }
+
+    // `Error::Stale` if the segment was created anew under this handle, e.g. by a
+    // restarted producer, so its messages and layout aren't what this handle expects.
+    // A fresh `create` resets both indices, so handles attaching afterwards never see
+    // slots from an earlier run and a per-slot stamp isn't needed.
+    #[inline(always)]
+    fn check_epoch(&self) -> Result<(), Error> {
+        if self.header().epoch.load(Ordering::Relaxed) != self.epoch {
+            return Err(Error::Stale);
+        }
+        Ok(())
+    }
+
+    // Number of slots in the ring
+    #[inline(always)]
+    fn capacity(&self) -> usize {
+        self.buffer_size / self.slot_size
+    }
+
+    // Longest message `write_message` takes: the slot less the length prefix, so
+    // `slot_size - 4` bytes with the default framing, but never more than 65534 with
+    // `PrefixWidth::U16`. A message of exactly this length fills the slot (or the
+    // prefix); one byte more is `Error::MessageTooLarge`.
+    fn max_message_len(&self) -> usize {
+        self.framing.max_message_len(self.slot_size)
+    }
+
+    // Whether this handle created the ring (`new`, `RingConfig::build`) rather than
+    // attaching to it, and so unlinks the segment when dropped
+    fn is_owner(&self) -> bool {
+        self.owner
+    }
+
+    // Unmap the ring and, if this handle created it (`is_owner`), unlink its
+    // segments now, like dropping it but returning the first OS error instead of
+    // ignoring it (everything is still attempted after a failure); see
+    // `close_when_drained` to unlink only once the consumer has caught up. Dropping
+    // the ring does the same teardown, best effort, unless `close` already ran.
+    fn close(mut self) -> Result<(), Error> {
+        self.teardown()
+    }
+
+    // The cleanup behind `close` and `Drop`, done at most once per handle
+    fn teardown(&mut self) -> Result<(), Error> {
+        if std::mem::replace(&mut self.closed, true) {
+            return Ok(());
+        }
+        self.release_held();
+        let mut result = Ok(());
+        let mut check = |what, failed: bool| {
+            if failed && result.is_ok() {
+                result = Err(Error::os(what, io::Error::last_os_error()));
+            }
+        };
+        unsafe {
+            match &self.data_segment {
+                None => {
+                    let len = DATA_OFFSET + Self::data_mapped_size(self.buffer_size);
+                    check("Failed to unmap shared memory", munmap(self.header as *mut libc::c_void, len) != 0);
+                }
+                Some(data_name) => {
+                    check("Failed to unmap shared memory", munmap(self.header as *mut libc::c_void, CONTROL_SIZE) != 0);
+                    let len = Self::data_mapped_size(self.buffer_size);
+                    check("Failed to unmap shared memory", munmap(self.buffer as *mut libc::c_void, len) != 0);
+                    if self.owner {
+                        check("Failed to unlink shared memory", shm_unlink(data_name.as_ptr()) != 0);
+                    }
+                }
+            }
+            if self.owner && !self.file_backed {
+                check("Failed to unlink shared memory", shm_unlink(self.name.0.as_ptr()) != 0);
+            }
+        }
+        result
+    }
+
+    // The name this ring was created or opened under, e.g. for logging
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    // The fd of the segment (or backing file) behind the ring, the control segment of a
+    // split ring, whose data segment is still opened by name. It stays owned by this
+    // handle, which closes it on drop: `dup` it (e.g. `BorrowedFd::try_clone_to_owned`)
+    // before handing it to a child across `exec` or over a Unix socket (see `HandleRing`).
+    fn raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    // The area reserved with `RingConfig::user_header`, shared by every attached
+    // process and zeroed by `create`. Nothing synchronizes it: the creator should
+    // fill it in with `user_header_mut` before peers open the ring, and any later
+    // update needs the application's own protocol (e.g. a version written last).
+    fn user_header(&self) -> &[u8] {
+        let len = self.header().user_len.load(Ordering::Relaxed).min(USER_HEADER_MAX);
+        unsafe { std::slice::from_raw_parts((self.header as *mut u8).add(HEADER_SIZE), len) }
+    }
+
+    // `user_header` for writing, with the same caveats
+    fn user_header_mut(&mut self) -> &mut [u8] {
+        let len = self.header().user_len.load(Ordering::Relaxed).min(USER_HEADER_MAX);
+        unsafe { std::slice::from_raw_parts_mut((self.header as *mut u8).add(HEADER_SIZE), len) }
+    }
+
+    // Move to a freshly created ring `new_name` holding `new_capacity` slots of the
+    // same size, copying over every message still unread in `old`. A mapping can't
+    // be resized in place, hence drain-and-copy: producers must be paused for the
+    // duration and then switched to the returned ring, or messages written to `old`
+    // after the drain are lost. The user header comes along; a message leaves `old`
+    // only once it is in the new ring, so on error what wasn't copied is still there.
+    fn migrate(old: &Self, new_name: &RingName, new_capacity: usize) -> Result<Self, Error> {
+        let write_idx = old.header().write_idx.load(Ordering::Acquire);
+        let pending = write_idx.wrapping_sub(old.header().read_idx.load(Ordering::Relaxed));
+        if pending / old.slot_size as u64 > new_capacity as u64 {
+            return Err(Error::InvalidConfig("new capacity can't hold the pending messages"));
+        }
+
+        let user_len = old.header().user_len.load(Ordering::Relaxed);
+        let mut new = RingConfig::new(new_name)
+            .slot_size(old.slot_size)
+            .capacity(new_capacity)
+            .endianness(old.endianness)
+            .zero_on_recycle(old.zero_on_recycle)
+            .framing(old.framing)
+            .user_header(user_len)
+            .build()?;
+        new.user_header_mut().copy_from_slice(old.user_header());
+        while !old.is_empty() {
+            let message = old.read_message_ref()?;
+            if let Err(e) = new.write_message(&message) {
+                // Leave the message unread in `old`
+                std::mem::forget(message);
+                return Err(e);
+            }
+        }
+        Ok(new)
+    }
+
+    // The indices are byte counters that only ever grow (slot = index % buffer_size),
+    // so their distance alone tells full (`capacity` slots apart) from empty (equal),
+    // no slot kept free. They're 64-bit on every target, so a wrap is 2^64 bytes of
+    // traffic away (a 32-bit counter would wrap every 4 GiB), and distances are taken
+    // with `wrapping_sub` anyway. `read_idx` is loaded Acquire: it pairs with
+    // the consumer's Release once it is done with a slot, so the producer never
+    // reuses a slot that is still being copied out.
+    #[inline(always)]
+    fn is_full(&self) -> bool {
+        let read_idx = self.header().read_idx.load(Ordering::Acquire);
+        let write_idx = self.header().write_idx.load(Ordering::Relaxed);
+        write_idx.wrapping_sub(read_idx) >= self.buffer_size as u64 - self.soft_full_reserve
+    }
+
+    #[inline(always)]
+    fn is_empty(&self) -> bool {
+        self.header().read_idx.load(Ordering::Relaxed) == self.header().write_idx.load(Ordering::Acquire)
+    }
+
+    // Messages waiting to be read. Snapshots like this and `writable` only tell the
+    // caller's own side what it can do without waiting: the other side can only make
+    // the real figure larger by the time it's used (short of a producer overwriting).
+    fn readable(&self) -> usize {
+        let read_idx = self.header().read_idx.load(Ordering::Acquire);
+        let write_idx = self.header().write_idx.load(Ordering::Acquire);
+        (write_idx.wrapping_sub(read_idx) / self.slot_size as u64) as usize
+    }
+
+    // Free slots, i.e. messages that can be written without waiting (short of
+    // `RingConfig::soft_full_reserve`)
+    fn writable(&self) -> usize {
+        self.capacity().saturating_sub(self.readable() + (self.soft_full_reserve / self.slot_size as u64) as usize)
+    }
+
+    // Check the counters for states normal operation can't reach (e.g. after
+    // attaching to a stale or trashed segment)
+    fn validate(&self) -> Result<(), Error> {
+        let read_idx = self.header().read_idx.load(Ordering::Acquire);
+        let write_idx = self.header().write_idx.load(Ordering::Acquire);
+        if write_idx.wrapping_sub(read_idx) > self.buffer_size as u64 {
+            return Err(Error::Corrupt("read_idx ahead of write_idx, or more slots in use than the ring holds"));
+        }
+        if !read_idx.is_multiple_of(self.slot_size as u64) || !write_idx.is_multiple_of(self.slot_size as u64) {
+            return Err(Error::Corrupt("index not aligned to a slot boundary"));
+        }
+        Ok(())
+    }
+
+    // Every slot of the data region with its length prefix checked, for inspecting a
+    // quiesced segment after a crash; `validate` checks the indices. Nothing is
+    // consumed and neither index is touched. Slots that aren't `unread` hold
+    // whatever was last in them (zeros in a fresh ring), so only for unread ones
+    // is a bad length corruption. A slot written to mid-scan may be reported torn.
+    fn scan(&self) -> impl Iterator<Item = SlotStatus> + '_ {
+        let read_idx = self.header().read_idx.load(Ordering::Acquire);
+        let unread_bytes = self.header().write_idx.load(Ordering::Acquire).wrapping_sub(read_idx);
+        let read_offset = self.slot_offset(read_idx);
+        (0..self.capacity()).map(move |slot| {
+            let offset = slot * self.slot_size;
+            let behind_read = (offset + self.buffer_size - read_offset) % self.buffer_size;
+            let len = match self.framing {
+                Framing::LengthPrefixed(_) => {
+                    let (_, len) = unsafe { self.framing.read_len(self.buffer.add(offset), self.endianness) };
+                    Some(len).filter(|&len| len <= self.max_message_len())
+                }
+                Framing::Fixed(len) => Some(len),
+            };
+            SlotStatus { slot, unread: (behind_read as u64) < unread_bytes, len }
+        })
+    }
+
+    // Every unread slot as one contiguous slice, even when it wraps past the end
+    // of the data region (the mirror mapping continues it from the start)
+    #[cfg(feature = "magic-ring")]
+    fn readable_region(&self) -> &[u8] {
+        let read_idx = self.header().read_idx.load(Ordering::Relaxed);
+        let write_idx = self.header().write_idx.load(Ordering::Acquire);
+        let start = self.slot_offset(read_idx);
+        unsafe { std::slice::from_raw_parts(self.buffer.add(start), write_idx.wrapping_sub(read_idx) as usize) }
+    }
+
+    // Every unread slot, as the run up to the end of the data region and the run
+    // continued from its start, like `VecDeque::as_slices`, for scatter-gather
+    // consumers (`writev`, `WSASend`) that take the two halves without a reassembly
+    // copy. The second slice is empty unless the unread slots wrap past the end; both
+    // are empty on an empty ring. Slots come whole, framing and padding included, and
+    // a slot never straddles the split, so each half is a whole number of slots.
+    // Release them with `advance_read`.
+    fn readable_slices(&self) -> (&[u8], &[u8]) {
+        let read_idx = self.header().read_idx.load(Ordering::Relaxed);
+        let write_idx = self.header().write_idx.load(Ordering::Acquire);
+        let start = self.slot_offset(read_idx);
+        let len = write_idx.wrapping_sub(read_idx) as usize;
+        let first = len.min(self.buffer_size - start);
+        unsafe {
+            (
+                std::slice::from_raw_parts(self.buffer.add(start), first),
+                std::slice::from_raw_parts(self.buffer, len - first),
+            )
+        }
+    }
+
+    // Release `slots` slots that were consumed through `readable_region` or `readable_slices`
+    fn advance_read(&self, slots: usize) {
+        let read_idx = self.header().read_idx.load(Ordering::Relaxed);
+        debug_assert!(((slots * self.slot_size) as u64) <= self.header().write_idx.load(Ordering::Acquire).wrapping_sub(read_idx));
+        self.header().read_idx.store(read_idx.wrapping_add((slots * self.slot_size) as u64), Ordering::Release);
+    }
+
+    // Make room for `write_message` by dropping the oldest unread message. Rings
+    // created with `zero_on_recycle` refuse, as the consumer clears the slot it just
+    // read and could wipe the message written over it.
+    fn discard_oldest(&self) -> Result<(), Error> {
+        if self.zero_on_recycle {
+            return Err(Error::InvalidConfig("can't overwrite messages in a zero_on_recycle ring"));
+        }
+        let read_idx = self.header().read_idx.load(Ordering::Acquire);
+        let next = read_idx.wrapping_add(self.slot_size as u64);
+        // Failing means the consumer got there first and freed the slot itself
+        if self.header().read_idx.compare_exchange(read_idx, next, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+            self.header().overflow_count.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    // Sequence number of the next message `read_message` returns (messages are
+    // numbered from 0 since the segment was created), to checkpoint consumer progress
+    fn read_cursor(&self) -> u64 {
+        self.header().read_idx.load(Ordering::Acquire) / self.slot_size as u64
+    }
+
+    // Resume reading at message `seq`, e.g. a `read_cursor` checkpoint taken before a
+    // crash. Going back only works while those slots haven't been reused, so `seq`
+    // must lie between the oldest message the producer can't be overwriting and the
+    // next one it will write; rings that zero slots on read can't go back at all.
+    // Only the consumer may call this, never concurrently with `read_message`.
+    fn set_read_cursor(&self, seq: u64) -> Result<(), Error> {
+        let next = self.header().write_idx.load(Ordering::Acquire) / self.slot_size as u64;
+        let oldest = if self.zero_on_recycle {
+            self.read_cursor()
+        } else {
+            // The producer may already be writing the slot of message `next - capacity`
+            (next + 1).saturating_sub(self.capacity() as u64)
+        };
+        if seq < oldest || seq > next {
+            return Err(Error::CursorOutOfRange { seq, oldest, next });
+        }
+        self.header().read_idx.store(seq * self.slot_size as u64, Ordering::Release);
+        Ok(())
+    }
+
+    // Tell consumers the producer has written its last message
+    fn mark_done(&self) {
+        self.header().producer_done.store(1, Ordering::Release);
+    }
+
+    // Whether the producer called `mark_done`. Messages written before that are
+    // visible to a consumer that sees `true`, so done and empty means fully drained.
+    fn is_done(&self) -> bool {
+        self.header().producer_done.load(Ordering::Acquire) != 0
+    }
+
+    // Wait (with the configured `WaitStrategy`) until the consumer has read every
+    // message, and only then close and unlink the ring: dropping the creator
+    // unlinks at once, under a consumer that may not have caught up. If the ring
+    // isn't empty within `timeout` it's closed without unlinking, so the unread
+    // messages survive for the consumer, and this returns `Error::TimedOut`.
+    fn close_when_drained(mut self, timeout: Duration) -> Result<(), Error> {
+        let since = Instant::now();
+        while !self.is_empty() {
+            if since.elapsed() >= timeout {
+                self.owner = false;
+                return Err(Error::TimedOut);
+            }
+            self.wait.wait(since, self.write_spin, Some(&self.header().read_idx));
+        }
+        self.close()
+    }
+
+    // Stamp the header with the time, from the producer, independently of the
+    // messages: call it periodically, and a monitor reading `last_heartbeat` can
+    // tell a dead producer from an idle one even with the ring empty
+    fn write_heartbeat(&self) {
+        self.header().heartbeat.store(monotonic_nanos(), Ordering::Release);
+    }
+
+    // When the producer last called `write_heartbeat`, as an `Instant` of this
+    // process, or `None` if it never has. The stamp is taken from a clock every
+    // process shares, so compare it with `Instant::now()` to decide staleness.
+    fn last_heartbeat(&self) -> Option<Instant> {
+        heartbeat_instant(self.header().heartbeat.load(Ordering::Acquire))
+    }
+
+    // Mark the ring poisoned, for whoever finds out the producer died (e.g. the
+    // parent reaping it): consumers still get every message it published, then
+    // `Error::Poisoned` instead of waiting forever on an empty ring. A slot is only
+    // published once fully written, so a producer dying mid-write leaves nothing
+    // half-written to skip, just the stall.
+    fn poison(&self) {
+        self.header().poisoned.store(1, Ordering::Release);
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.header().poisoned.load(Ordering::Acquire) != 0
+    }
+
+    // Ask every process attached to the ring to wind down, e.g. from an orchestrator
+    // stopping them all. It is cooperative: nothing is interrupted or unmapped, but
+    // from now on every call that would wait (a read on an empty ring, a write to a
+    // full one, `wait_readable`, `drain_bounded`, `write_message_acked`) returns
+    // `Error::Shutdown` instead, checked between waits. Calls that don't need to wait
+    // still succeed, so consumers can drain what is left, and processes that never
+    // block have to check `is_shutdown` themselves. Holds until the ring is created
+    // anew.
+    fn request_shutdown(&self) {
+        self.header().shutdown.store(1, Ordering::Release);
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.header().shutdown.load(Ordering::Acquire) != 0
+    }
+
+    // The first `N` payload bytes of the oldest unread message, without consuming it.
+    // Consumer side only.
+    fn peek_prefix<const N: usize>(&self) -> Option<[u8; N]> {
+        if N > self.slot_size - self.framing.overhead() || self.is_empty() {
+            return None;
+        }
+        let start = self.slot_offset(self.header().read_idx.load(Ordering::Relaxed));
+        let mut prefix = [0u8; N];
+        unsafe { ptr::copy_nonoverlapping(self.buffer.add(start + self.framing.overhead()), prefix.as_mut_ptr(), N) };
+        Some(prefix)
+    }
+
+    // The most messages the ring has held unread at once since it was created, as
+    // seen by the producer right after each write, for sizing `capacity`: reaching
+    // `capacity()` means a write found (or would have found) the ring full. Not
+    // cleared by `reset_stats`.
+    fn high_water_mark(&self) -> usize {
+        (self.header().max_occupancy.load(Ordering::Relaxed) as usize).min(self.capacity())
+    }
+
+    // Bytes this handle's reads have skipped past corrupt slots, with
+    // `RingConfig::resync_on_corruption`; a multiple of the slot size unless a read
+    // index off a slot boundary was realigned
+    fn resynced_bytes(&self) -> u64 {
+        self.resynced.load(Ordering::Relaxed)
+    }
+
+    // Counters shared by every process attached to the segment
+    fn stats(&self) -> Stats {
+        let header = self.header();
+        let written = header.write_idx.load(Ordering::Relaxed) / self.slot_size as u64;
+        let read = header.read_idx.load(Ordering::Relaxed) / self.slot_size as u64;
+        Stats {
+            written: written.saturating_sub(header.written_base.load(Ordering::Relaxed)),
+            read: read.saturating_sub(header.read_base.load(Ordering::Relaxed)),
+            overflows: header.overflow_count.load(Ordering::Relaxed),
+            stalls: header.stall_count.load(Ordering::Relaxed),
+        }
+    }
+
+    // `stats`, then start every counter again from zero (for every attached process),
+    // e.g. to export per-interval deltas. Each counter is moved on atomically, so nothing
+    // is lost or counted twice across resets, even from several processes, but the
+    // three aren't reset as one: traffic while this runs can land in this snapshot for
+    // one counter and in the next for another, which is fine for monitoring.
+    fn snapshot_and_reset(&self) -> Stats {
+        let header = self.header();
+        let written = header.write_idx.load(Ordering::Relaxed) / self.slot_size as u64;
+        let read = header.read_idx.load(Ordering::Relaxed) / self.slot_size as u64;
+        Stats {
+            written: written.saturating_sub(header.written_base.fetch_max(written, Ordering::Relaxed)),
+            read: read.saturating_sub(header.read_base.fetch_max(read, Ordering::Relaxed)),
+            overflows: header.overflow_count.swap(0, Ordering::Relaxed),
+            stalls: header.stall_count.swap(0, Ordering::Relaxed),
+        }
+    }
+
+    // Start the `stats` counters again from zero, see `snapshot_and_reset`
+    fn reset_stats(&self) {
+        self.snapshot_and_reset();
+    }
+
+    // Report the ring to OpenTelemetry through `meter`: `ring.occupancy` (messages
+    // waiting, a gauge) and the `stats` counters `ring.messages.written`,
+    // `ring.messages.read`, `ring.stalls` and `ring.drops`, each labelled with
+    // `ring` = the ring name plus `attributes`. They're observed on every collection
+    // for as long as the ring is alive. The counters are cumulative, so don't mix
+    // this with `reset_stats`.
+    #[cfg(feature = "otel")]
+    fn register_metrics(self: &Arc<Self>, meter: &opentelemetry::metrics::Meter, attributes: &[opentelemetry::KeyValue]) {
+        use opentelemetry::metrics::AsyncInstrument;
+
+        let mut labels = vec![opentelemetry::KeyValue::new("ring", self.name.to_string())];
+        labels.extend_from_slice(attributes);
+        let labels: Arc<[opentelemetry::KeyValue]> = labels.into();
+        let observe = |value: fn(&Self) -> u64| {
+            let ring = Arc::downgrade(self);
+            let labels = labels.clone();
+            move |instrument: &dyn AsyncInstrument<u64>| {
+                if let Some(ring) = ring.upgrade() {
+                    instrument.observe(value(&ring), &labels);
+                }
+            }
+        };
+        meter
+            .u64_observable_gauge("ring.occupancy")
+            .with_description("Messages waiting to be read")
+            .with_unit("{message}")
+            .with_callback(observe(|ring| ring.readable() as u64))
+            .build();
+        meter
+            .u64_observable_counter("ring.messages.written")
+            .with_description("Messages published")
+            .with_unit("{message}")
+            .with_callback(observe(|ring| ring.stats().written))
+            .build();
+        meter
+            .u64_observable_counter("ring.messages.read")
+            .with_description("Messages consumed")
+            .with_unit("{message}")
+            .with_callback(observe(|ring| ring.stats().read))
+            .build();
+        meter
+            .u64_observable_counter("ring.stalls")
+            .with_description("Writes that waited for a free slot")
+            .with_unit("{write}")
+            .with_callback(observe(|ring| ring.stats().stalls as u64))
+            .build();
+        meter
+            .u64_observable_counter("ring.drops")
+            .with_description("Messages dropped because they didn't fit")
+            .with_unit("{message}")
+            .with_callback(observe(|ring| ring.stats().overflows as u64))
+            .build();
+    }
+
+    // Queue `message`, waiting (or not, see `FullPolicy`) while the ring is full. An
+    // empty message is valid, e.g. as a heartbeat tick: it takes a slot like any other
+    // and reads back as an empty `Vec`. Returns the bytes of `message` written, which is
+    // all of them (the framing isn't counted), like a successful `io::Write::write_all`.
+    #[inline(always)]
+    fn write_message(&self, message: &[u8]) -> Result<usize, Error> {
+        self.write_prefixed(&[], message, None)
+    }
+
+    // `write_message` that never waits: `Error::Full` at once if no slot is free,
+    // whatever the `FullPolicy` (a hook isn't called). It is wait-free and
+    // async-signal-safe, for use in a signal handler (e.g. to record a crash): no
+    // allocation, no locks, no clock or other syscalls and no tracing, only atomic
+    // loads and stores and the copy into the slot. The code it interrupts mustn't be
+    // writing to the same ring.
+    fn try_write_message(&self, message: &[u8]) -> Result<usize, Error> {
+        if self.is_full() {
+            self.header().overflow_count.fetch_add(1, Ordering::Relaxed);
+            return Err(Error::Full);
+        }
+        // Only the producer fills slots, so `claim` finds this one still free
+        let (write_idx, slot) = self.claim(0, message.len(), None)?;
+        unsafe { ptr::copy_nonoverlapping(message.as_ptr(), slot.add(self.framing.overhead()), message.len()) };
+        // Not `publish_slot`, whose debug asserts format and panic; `claim` checked the length
+        self.publish_framed(write_idx, message.len());
+        Ok(message.len())
+    }
+
+    // `write_message`, calling `on_wait` in place of each wait step on a full ring,
+    // so the producer can get other work done (flush a log, check a control flag)
+    // while the consumer catches up. `on_wait` sets the pace of the retries.
+    fn write_message_with(&self, message: &[u8], mut on_wait: impl FnMut()) -> Result<usize, Error> {
+        self.write_prefixed(&[], message, Some(&mut || {
+            on_wait();
+            Ok(())
+        }))
+    }
+
+    // `write_message` that gives up with `Error::Cancelled` once `cancel` is set,
+    // e.g. by a watchdog thread or any other event, checked between waits on a full
+    // ring. A message that fits without waiting is written whatever the flag says.
+    fn write_message_cancelable(&self, message: &[u8], cancel: &AtomicBool) -> Result<usize, Error> {
+        let mut waiting_since = None;
+        self.write_prefixed(&[], message, Some(&mut || {
+            if cancel.load(Ordering::Acquire) {
+                return Err(Error::Cancelled);
+            }
+            let since = *waiting_since.get_or_insert_with(Instant::now);
+            self.wait.wait(since, self.write_spin, Some(&self.header().read_idx));
+            Ok(())
+        }))
+    }
+
+    // `write_message` with a one-byte type `tag` in front, for rings multiplexing
+    // several kinds of message; read them back with `read_message_filtered`. The tag
+    // takes a byte of the slot payload and isn't counted in the returned length.
+    fn write_message_typed(&self, tag: u8, message: &[u8]) -> Result<usize, Error> {
+        self.write_prefixed(&[tag], message, None)
+    }
+
+    // `write_message` with an `H` header in front (see `SlotHeader`), carrying `tag`
+    // if its layout has one; read it back with `read_message_framed::<H>`. The
+    // header takes `H::SIZE` bytes of the slot payload and isn't counted in the
+    // returned length.
+    fn write_message_framed<H: SlotHeader>(&self, message: &[u8], tag: u32) -> Result<usize, Error> {
+        const { assert!(H::SIZE <= SLOT_HEADER_MAX) };
+        let mut header = [0u8; SLOT_HEADER_MAX];
+        H::write(&mut header[..H::SIZE], message, tag);
+        self.write_prefixed(&header[..H::SIZE], message, None)
+    }
+
+    // `write_message`, then wait up to `timeout` for the consumer to `ack` it. The
+    // fire-and-forget path is unaffected.
+    fn write_message_acked(&self, message: &[u8], timeout: Duration) -> Result<usize, Error> {
+        let seq = self.header().write_idx.load(Ordering::Relaxed) / self.slot_size as u64;
+        let written = self.write_message(message)?;
+
+        let deadline = Instant::now() + timeout;
+        let waiting_since = Instant::now();
+        while self.header().acked.load(Ordering::Acquire) <= seq {
+            if Instant::now() >= deadline {
+                return Err(Error::TimedOut);
+            }
+            if self.is_shutdown() {
+                return Err(Error::Shutdown);
+            }
+            self.wait.wait(waiting_since, self.write_spin, Some(&self.header().acked));
+        }
+        Ok(written)
+    }
+
+    // Acknowledge message `seq` and every one before it, releasing producers blocked
+    // in `write_message_acked`. A message's `seq` is the `read_cursor` just before
+    // `read_message` returned it.
+    fn ack(&self, seq: u64) {
+        self.header().acked.fetch_max(seq + 1, Ordering::Release);
+    }
+
+    // A release fence (`fence(Ordering::Release)`), for producers that compose their
+    // own publish sequence out of plain writes to the mapping (slots filled through
+    // `Reservation`s, `user_header_mut`) and atomic stores of their own. Every write
+    // made by this thread before the barrier is visible to any thread of any process
+    // that acquire-loads a value stored by an atomic store after it, even a `Relaxed`
+    // one. It orders nothing on its own: without such a store there is nothing for a
+    // reader to synchronize with, and it doesn't wait for or flush anything. The ring's
+    // own publishes (`write_message`, `Reservation::publish`) already store `write_idx`
+    // with `Release`, so they never need it.
+    #[inline(always)]
+    fn publish_barrier(&self) {
+        std::sync::atomic::fence(Ordering::Release);
+    }
+
+    // Back out of `reservation` without publishing it, for a producer that finds out
+    // halfway through (a serializer failing) that the slot holds garbage: the slot
+    // is handed on marked as a tombstone, which `read_message` and the other reads skip
+    // without returning it, rather than held back, which would make the next write
+    // claim it again. Until skipped it still counts in `readable`, and a consumer
+    // peeking with `peek_prefix` sees its bytes. `Framing::Fixed` rings have no
+    // prefix to mark, so there this is `Error::InvalidConfig`.
+    fn abandon_slot(&self, reservation: Reservation<'_>) -> Result<(), Error> {
+        let tombstone = self
+            .framing
+            .tombstone()
+            .ok_or(Error::InvalidConfig("a ring with fixed-length messages can't mark a slot abandoned"))?;
+        self.publish_framed(reservation.write_idx, tombstone);
+        Ok(())
+    }
+
+    // Claim a slot for a `len`-byte message to be filled in place, e.g. when a length
+    // or checksum field can only be set once the rest is written. Waits (or not, see
+    // `FullPolicy`) while the ring is full; nothing reaches the consumer until
+    // `Reservation::publish`. The bytes start out as whatever the slot last held.
+    fn reserve(&self, len: usize) -> Result<Reservation<'_>, Error> {
+        self.pace();
+        let (write_idx, slot) = self.claim(0, len, None)?;
+        let payload = unsafe { std::slice::from_raw_parts_mut(slot.add(self.framing.overhead()), len) };
+        Ok(Reservation { ring: self, write_idx, payload })
+    }
+
+    // `write_message` of `prefix` followed by `message`, without joining them first
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "write_message", level = "trace", skip_all))]
+    fn write_prefixed(&self, prefix: &[u8], message: &[u8], on_wait: Option<&mut dyn FnMut() -> Result<(), Error>>) -> Result<usize, Error> {
+        #[cfg(feature = "tracing")]
+        let blocked = self.is_full();
+        #[cfg(feature = "tracing")]
+        if blocked {
+            tracing::debug!("ring full, waiting for the consumer");
+        }
+
+        self.pace();
+        let (write_idx, slot) = self.claim(prefix.len(), message.len(), on_wait)?;
+        let len = prefix.len() + message.len();
+        self.debug_check_slot(write_idx);
+        debug_assert!(len <= self.max_message_len(), "message of {} bytes overruns the slot", len);
+        unsafe {
+            let offset = self.framing.overhead();
+            ptr::copy_nonoverlapping(prefix.as_ptr(), slot.add(offset), prefix.len());
+            ptr::copy_nonoverlapping(message.as_ptr(), slot.add(offset + prefix.len()), message.len());
+        }
+        self.publish_slot(write_idx, len);
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(slot = self.slot_offset(write_idx) / self.slot_size, len, blocked, "message written");
+        Ok(message.len())
+    }
+
+    // Hold the producer to `RingConfig::rate_limit`, if any
+    #[inline(always)]
+    fn pace(&self) {
+        let Some(limiter) = &self.rate_limit else {
+            return;
+        };
+        let mut waiting_since = None;
+        while let Err(wait) = limiter.try_acquire() {
+            if wait > self.write_spin {
+                thread::sleep(wait - self.write_spin);
+            } else {
+                self.wait.wait(*waiting_since.get_or_insert_with(Instant::now), self.write_spin, None);
+            }
+        }
+    }
+
+    // Wait (or not, see `FullPolicy`) for a free slot to write `prefix_len +
+    // message_len` payload bytes to, as its index and address
+    fn claim(
+        &self,
+        prefix_len: usize,
+        message_len: usize,
+        mut on_wait: Option<&mut dyn FnMut() -> Result<(), Error>>,
+    ) -> Result<(u64, *mut u8), Error> {
+        if !self.unchecked {
+            let max = self.max_message_len().saturating_sub(prefix_len);
+            if message_len > max {
+                self.header().overflow_count.fetch_add(1, Ordering::Relaxed);
+                return Err(Error::MessageTooLarge { len: message_len, max });
+            }
+            self.check_epoch()?;
+            let len = prefix_len + message_len;
+            if let Framing::Fixed(expected) = self.framing {
+                if len != expected {
+                    return Err(Error::WrongLength { len, expected });
+                }
+            }
+        }
+
+        let mut waiting_since = None;
+        while self.is_full() {
+            match self.full.action() {
+                FullAction::Retry => {
+                    if self.is_shutdown() {
+                        return Err(Error::Shutdown);
+                    }
+                    if waiting_since.is_none() {
+                        self.header().stall_count.fetch_add(1, Ordering::Relaxed);
+                    }
+                    let since = *waiting_since.get_or_insert_with(Instant::now);
+                    match on_wait.as_mut() {
+                        Some(on_wait) => on_wait()?,
+                        None => self.wait.wait(since, self.write_spin, Some(&self.header().read_idx)),
+                    }
+                }
+                FullAction::Drop => {
+                    self.header().overflow_count.fetch_add(1, Ordering::Relaxed);
+                    return Err(Error::Full);
+                }
+                FullAction::Overwrite => self.discard_oldest()?,
+            }
+        }
+
+        let write_idx = self.header().write_idx.load(Ordering::Relaxed);
+        let start = self.slot_offset(write_idx);
+
+        // The one bounds check: with the payload already checked against the slot,
+        // every write to it stays inside this slot
+        if !self.unchecked && start + self.slot_size > self.buffer_size {
+            return Err(Error::Corrupt("slot runs past the end of the buffer"));
+        }
+        Ok((write_idx, unsafe { self.buffer.add(start) }))
+    }
+
+    // Frame the `len` payload bytes written to the slot at `write_idx` and hand it to
+    // the consumer
+    fn publish_slot(&self, write_idx: u64, len: usize) {
+        self.debug_check_slot(write_idx);
+        debug_assert!(len <= self.max_message_len(), "message of {} bytes overruns the slot", len);
+        self.publish_framed(write_idx, len);
+    }
+
+    // Store `len`, a message length or the `tombstone`, in the prefix of the slot at
+    // `write_idx` and hand the slot to the consumer
+    fn publish_framed(&self, write_idx: u64, len: usize) {
+        unsafe { self.framing.write_len(self.buffer.add(self.slot_offset(write_idx)), len, self.endianness) };
+        let next = write_idx.wrapping_add(self.slot_size as u64);
+        self.header().write_idx.store(next, Ordering::Release);
+        // Only an actual new maximum pays for the read-modify-write
+        let occupancy = next.wrapping_sub(self.header().read_idx.load(Ordering::Relaxed)) / self.slot_size as u64;
+        if occupancy > self.header().max_occupancy.load(Ordering::Relaxed) {
+            self.header().max_occupancy.fetch_max(occupancy, Ordering::Relaxed);
+        }
+    }
+
+    // Take the oldest message, waiting for one if the ring is empty. The `Vec` holds
+    // exactly the bytes that were written (`len` and `capacity` both equal to the
+    // message length, never the slot padding), and an empty message comes back as
+    // an empty `Vec`.
+    fn read_message(&self) -> Result<Vec<u8>, Error> {
+        self.read_message_hinted(self.wait)
+    }
+
+    // `read_message`, waiting on an empty ring with `wait` instead of the handle's
+    // own strategy for this one call: e.g. `WaitStrategy::Spin` on a latency-critical
+    // path, or `SpinThenPark` with a long `park` for a background drain, all on
+    // the same handle
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "read_message", level = "trace", skip_all))]
+    fn read_message_hinted(&self, wait: WaitStrategy) -> Result<Vec<u8>, Error> {
+        #[cfg(feature = "tracing")]
+        let blocked = self.is_empty();
+        #[cfg(feature = "tracing")]
+        if blocked {
+            tracing::debug!("ring empty, waiting for the producer");
+        }
+
+        // A producer told to overwrite can move read_idx under us, in which case the
+        // slot may have been rewritten mid-copy: drop the copy and read the new oldest
+        loop {
+            let slot = self.read_slot_with(wait, None)?;
+            let message = slot.to_vec();
+            #[cfg(feature = "tracing")]
+            let index = self.slot_offset(slot.read_idx) / self.slot_size;
+            if slot.release() {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(slot = index, len = message.len(), blocked, "message read");
+                return Ok(message);
+            }
+        }
+    }
+
+    // `read_message` that gives up with `Error::Cancelled` once `cancel` is set,
+    // checked between waits on an empty ring, so another thread can call off a
+    // read on any event rather than only a timeout
+    fn read_message_cancelable(&self, cancel: &AtomicBool) -> Result<Vec<u8>, Error> {
+        loop {
+            let slot = self.read_slot_with(self.wait, Some(cancel))?;
+            let message = slot.to_vec();
+            if slot.release() {
+                return Ok(message);
+            }
+        }
+    }
+
+    // `read_message` into `buf`, replacing what it held, for a consumer that reads in a
+    // loop: the message is copied out of its slot once and `buf` keeps its allocation
+    // across calls, so a steady stream of messages no larger than the biggest so far
+    // allocates nothing. Safe whatever the producer's `FullPolicy`, like `read_message`
+    // and unlike `read_message_ref`. Returns the message length.
+    fn read_message_into(&self, buf: &mut Vec<u8>) -> Result<usize, Error> {
+        loop {
+            let slot = self.read_slot_with(self.wait, None)?;
+            buf.clear();
+            buf.extend_from_slice(&slot);
+            if slot.release() {
+                return Ok(buf.len());
+            }
+        }
+    }
+
+    // `read_message` into a buffer taken from `pool`, which gets it back when the
+    // `PooledBuf` drops: a middle ground between a fresh `Vec` per read and
+    // `read_message_ref`, whose borrow pins the slot. Once the pool has warmed up,
+    // reads allocate only while more buffers are out than it keeps.
+    fn read_message_pooled(&self, pool: &BufferPool) -> Result<PooledBuf, Error> {
+        let mut buf = pool.take();
+        self.read_message_into(&mut buf)?;
+        Ok(PooledBuf { buf, pool: pool.clone() })
+    }
+
+    // The oldest message borrowed in place, waiting for one if the ring is empty. No
+    // copy is made; the slot is freed when the guard drops. Not for rings whose
+    // producer may overwrite (`FullPolicy::Overwrite`, or a hook choosing it): it
+    // could rewrite the slot under the borrow, which `read_message` detects and this
+    // can't.
+    fn read_message_ref(&self) -> Result<SlotRef<'_>, Error> {
+        self.read_slot()
+    }
+
+    // The oldest message, borrowed from its slot when that is safe and copied when it
+    // isn't, for code that mostly reads and forwards but sometimes keeps a message.
+    // A borrowed message keeps its slot unread until the next read on this handle
+    // (or its drop), so meanwhile it still counts in `readable` and a producer can't reuse
+    // it; `&mut self` stops this handle from reading again while the borrow lives.
+    // Borrowing is only sound if this handle is the sole consumer and the producer
+    // never overwrites unread slots; both are the caller's promise, as for
+    // `read_message_ref`. The producer's policy isn't visible from here, so a handle
+    // whose own `RingConfig::full` is `FullPolicy::Overwrite` or a hook always gets
+    // a copy (`Cow::Owned`): open the consumer with the producer's policy.
+    fn read_message_cow(&mut self) -> Result<Cow<'_, [u8]>, Error> {
+        if !matches!(self.full, FullPolicy::Block | FullPolicy::Drop) {
+            return self.read_message().map(Cow::Owned);
+        }
+        let slot = self.read_slot()?;
+        let (read_idx, message) = (slot.read_idx, slot.message);
+        std::mem::forget(slot);
+        self.held.store(read_idx, Ordering::Relaxed);
+        Ok(Cow::Borrowed(message))
+    }
+
+    // Free the slot `read_message_cow` lent out, if any
+    fn release_held(&self) {
+        let held = self.held.swap(NO_HELD_SLOT, Ordering::Relaxed);
+        if held != NO_HELD_SLOT {
+            self.release_slot(held);
+        }
+    }
+
+    // The next message written by `write_message_typed` whose tag `wanted` accepts,
+    // as `(tag, message)`. Messages with other tags are consumed and dropped on the
+    // way, not requeued, so this suits a single consumer that only cares about some
+    // kinds; the tag is checked in place, before anything is copied.
+    fn read_message_filtered(&self, wanted: impl Fn(u8) -> bool) -> Result<(u8, Vec<u8>), Error> {
+        loop {
+            let slot = self.read_slot()?;
+            let Some(&tag) = slot.first() else {
+                return Err(Error::Corrupt("typed message without its tag"));
+            };
+            if !wanted(tag) {
+                slot.release();
+                continue;
+            }
+            let message = slot[1..].to_vec();
+            if slot.release() {
+                return Ok((tag, message));
+            }
+        }
+    }
+
+    // The next message written by `write_message_framed::<H>`, with what its header
+    // recorded. The header is decoded in place and the message copied only if it
+    // checks out; a consumed message that doesn't is `Error::Corrupt`, or skipped
+    // with `RingConfig::resync_on_corruption`.
+    fn read_message_framed<H: SlotHeader>(&self) -> Result<(Frame, Vec<u8>), Error> {
+        loop {
+            let slot = self.read_slot()?;
+            let frame = if slot.len() < H::SIZE {
+                Err(Error::Corrupt("framed message shorter than its header"))
+            } else {
+                let (header, message) = slot.split_at(H::SIZE);
+                H::read(header, message).map(|frame| (frame, message.to_vec()))
+            };
+            // Overwritten mid-read, so the header may have been checked against
+            // another message: read the new oldest
+            if slot.release() {
+                match frame {
+                    Err(Error::Corrupt(_)) if self.resync_on_corruption => {
+                        self.resynced.fetch_add(self.slot_size as u64, Ordering::Relaxed);
+                    }
+                    frame => return frame,
+                }
+            }
+        }
+    }
+
+    // Read the next message written by `write_message_typed` and hand its payload to
+    // the handler registered for its tag, or to the default handler; returns the tag.
+    // Saves consumers the `match` over `read_message_filtered` (`dispatch` feature).
+    #[cfg(feature = "dispatch")]
+    fn dispatch(&self, handlers: &mut Handlers<'_>) -> Result<u8, Error> {
+        let (tag, message) = self.read_message_filtered(|_| true)?;
+        match &mut handlers.by_tag[tag as usize] {
+            Some(handler) => handler(&message),
+            None => (handlers.default)(tag, &message),
+        }
+        Ok(tag)
+    }
+
+    // Wait (with the configured `WaitStrategy`) until at least `n` messages are
+    // readable or `timeout` passes, and return how many are, so a batch consumer
+    // wakes once per batch and then takes it with `drain_bounded`. The count may be
+    // short of `n` after a timeout, or once the producer is done or poisoned (with
+    // nothing left at all, a poisoned ring is `Error::Poisoned`).
+    fn wait_readable(&self, n: usize, timeout: Duration) -> Result<usize, Error> {
+        let since = Instant::now();
+        loop {
+            let readable = self.readable();
+            if readable >= n || since.elapsed() >= timeout || self.is_done() {
+                return Ok(readable);
+            }
+            if self.is_poisoned() {
+                return if readable > 0 { Ok(readable) } else { Err(Error::Poisoned) };
+            }
+            if self.is_shutdown() {
+                return Err(Error::Shutdown);
+            }
+            self.wait.wait(since, self.read_spin, Some(&self.header().write_idx));
+        }
+    }
+
+    // Up to `max_count` messages as one batch, for consumers that amortize work per
+    // batch. Waits (with the configured `WaitStrategy`) up to `max_wait` for the first
+    // message, then takes only what is already in the ring; empty if nothing came in
+    // time. An error after the first message ends the batch early instead, and shows
+    // up again on the next call.
+    fn drain_bounded(&self, max_count: usize, max_wait: Duration) -> Result<Vec<Vec<u8>>, Error> {
+        let mut batch = Vec::new();
+        if max_count == 0 {
+            return Ok(batch);
+        }
+        let since = Instant::now();
+        while self.is_empty() {
+            if self.is_poisoned() {
+                return Err(Error::Poisoned);
+            }
+            if since.elapsed() >= max_wait {
+                return Ok(batch);
+            }
+            if self.is_shutdown() {
+                return Err(Error::Shutdown);
+            }
+            self.wait.wait(since, self.read_spin, Some(&self.header().write_idx));
+        }
+        while batch.len() < max_count && !self.is_empty() {
+            match self.read_message() {
+                Ok(message) => batch.push(message),
+                Err(e) if batch.is_empty() => return Err(e),
+                Err(_) => break,
+            }
+        }
+        Ok(batch)
+    }
+
+    // Overwrite slot `index` with `message`, using the ring as a table of `capacity`
+    // records instead of a queue: `read_idx` and `write_idx` are neither checked nor
+    // moved, so there's no FIFO order, no waiting and no `FullPolicy`, and the
+    // queue calls mustn't be used on the same ring. Each slot starts with a version,
+    // odd while a write is under way, so concurrent writers to one slot take turns
+    // and `read_slot_at` never returns a torn record. The slot holds
+    // `max_message_len() - 8` bytes; `slot_size` must be a multiple of 8.
+    fn write_slot_at(&self, index: usize, message: &[u8]) -> Result<(), Error> {
+        let (version, slot) = self.table_slot(index)?;
+        let max = self.framing.max_message_len(self.slot_size - 8);
+        if message.len() > max {
+            return Err(Error::MessageTooLarge { len: message.len(), max });
+        }
+        if let Framing::Fixed(expected) = self.framing {
+            if message.len() != expected {
+                return Err(Error::WrongLength { len: message.len(), expected });
+            }
+        }
+
+        let mut current = version.load(Ordering::Relaxed);
+        loop {
+            if current % 2 == 1 {
+                std::hint::spin_loop();
+                current = version.load(Ordering::Relaxed);
+                continue;
+            }
+            match version.compare_exchange_weak(current, current + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(seen) => current = seen,
+            }
+        }
+        // Keep the record writes after the odd version
+        std::sync::atomic::fence(Ordering::Release);
+        unsafe {
+            self.framing.write_len(slot, message.len(), self.endianness);
+            ptr::copy_nonoverlapping(message.as_ptr(), slot.add(self.framing.overhead()), message.len());
+        }
+        version.store(current + 2, Ordering::Release);
+        Ok(())
+    }
+
+    // The record in slot `index` and how many times it has been written (0, with an
+    // empty record, for a slot `write_slot_at` never touched), retrying while a
+    // writer is in the middle of it. See `write_slot_at`.
+    fn read_slot_at(&self, index: usize) -> Result<(u64, Vec<u8>), Error> {
+        let (version, slot) = self.table_slot(index)?;
+        let max = self.framing.max_message_len(self.slot_size - 8);
+        loop {
+            let before = version.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let (offset, len) = unsafe { self.framing.read_len(slot, self.endianness) };
+            let message = (len <= max).then(|| unsafe { std::slice::from_raw_parts(slot.add(offset), len) }.to_vec());
+            // Keep the record reads before the second look at the version
+            std::sync::atomic::fence(Ordering::Acquire);
+            if version.load(Ordering::Relaxed) == before {
+                return match message {
+                    Some(message) => Ok((before / 2, message)),
+                    None => Err(Error::Corrupt("message length exceeds slot")),
+                };
+            }
+        }
+    }
+
+    // The version word and the record area after it of table slot `index`
+    fn table_slot(&self, index: usize) -> Result<(&AtomicU64, *mut u8), Error> {
+        if index >= self.capacity() {
+            return Err(Error::SlotOutOfRange { index, capacity: self.capacity() });
+        }
+        self.table_layout.map_err(Error::InvalidConfig)?;
+        let slot = unsafe { self.buffer.add(index * self.slot_size) };
+        Ok((unsafe { &*(slot as *const AtomicU64) }, unsafe { slot.add(8) }))
+    }
+
+    // The read logic shared by `read_message` and `read_message_ref`
+    fn read_slot(&self) -> Result<SlotRef<'_>, Error> {
+        self.read_slot_with(self.wait, None)
+    }
+
+    // `read_slot`, waiting with `wait`
+    fn read_slot_with(&self, wait: WaitStrategy, cancel: Option<&AtomicBool>) -> Result<SlotRef<'_>, Error> {
+        self.release_held();
+        let mut waiting_since = None;
+        loop {
+            while self.is_empty() {
+                if self.is_poisoned() {
+                    return Err(Error::Poisoned);
+                }
+                if self.is_shutdown() {
+                    return Err(Error::Shutdown);
+                }
+                if cancel.is_some_and(|cancel| cancel.load(Ordering::Acquire)) {
+                    return Err(Error::Cancelled);
+                }
+                let since = *waiting_since.get_or_insert_with(Instant::now);
+                wait.wait(since, self.read_spin, Some(&self.header().write_idx));
+            }
+            self.check_epoch()?;
+
+            let read_idx = self.header().read_idx.load(Ordering::Relaxed);
+            if let Some(skipped) = self.lapped_by(read_idx)? {
+                if !self.skip_lapped {
+                    return Err(Error::Lapped { skipped });
+                }
+                continue;
+            }
+            let start = self.slot_offset(read_idx);
+
+            if !self.unchecked {
+                if start + self.slot_size > self.buffer_size {
+                    self.corrupt_at(read_idx, "slot runs past the end of the buffer")?;
+                    continue;
+                }
+                if !start.is_multiple_of(self.slot_size) {
+                    self.corrupt_at(read_idx, "read index is not on a slot boundary")?;
+                    continue;
+                }
+            }
+            let slot = unsafe { self.buffer.add(start) };
+            let (offset, message_len) = unsafe { self.framing.read_len(slot, self.endianness) };
+            if self.framing.tombstone() == Some(message_len) {
+                self.release_slot(read_idx);
+                continue;
+            }
+
+            if !self.unchecked && message_len > self.slot_size - offset {
+                if self.header().read_idx.load(Ordering::Acquire) != read_idx {
+                    continue;
+                }
+                self.corrupt_at(read_idx, "message length exceeds slot")?;
+                continue;
+            }
+
+            self.debug_check_slot(read_idx);
+            debug_assert!(offset + message_len <= self.slot_size, "message of {} bytes overruns the slot", message_len);
+            let message = unsafe { std::slice::from_raw_parts(slot.add(offset), message_len) };
+            return Ok(SlotRef { ring: self, read_idx, message });
+        }
+    }
+
+    // `FullPolicy::Overwrite` moves the read index itself as it discards the oldest
+    // message, so the unread span never exceeds the buffer; a producer that overwrites
+    // without doing so (another implementation of the layout, or indices scribbled over)
+    // can leave `read_idx` more than a lap behind, reading slots already rewritten.
+    // Move it up to the oldest slot still intact and return how many were skipped,
+    // or None when the consumer wasn't lapped (or another one already caught up).
+    // A producer can't get more than one extra lap ahead, so indices further apart
+    // than two laps (the write index behind the read index included) are corrupt.
+    fn lapped_by(&self, read_idx: u64) -> Result<Option<u64>, Error> {
+        let buffer_size = self.buffer_size as u64;
+        let unread = self.header().write_idx.load(Ordering::Acquire).wrapping_sub(read_idx);
+        if unread <= buffer_size {
+            return Ok(None);
+        }
+        if unread > 2 * buffer_size {
+            // Unless a reader moved `read_idx` since it was loaded
+            if self.header().read_idx.load(Ordering::Acquire) != read_idx {
+                return Ok(None);
+            }
+            return Err(Error::Corrupt("read_idx more than two laps behind write_idx"));
+        }
+        let skipped = (unread - buffer_size).div_ceil(self.slot_size as u64);
+        let oldest = read_idx.wrapping_add(skipped * self.slot_size as u64);
+        let caught_up =
+            self.header().read_idx.compare_exchange(read_idx, oldest, Ordering::AcqRel, Ordering::Relaxed).is_ok();
+        if !caught_up {
+            return Ok(None);
+        }
+        self.header().overflow_count.fetch_add(skipped as usize, Ordering::Relaxed);
+        Ok(Some(skipped))
+    }
+
+    // `Error::Corrupt(what)` for the slot at `read_idx`, or with
+    // `RingConfig::resync_on_corruption` skip it: move `read_idx` up to the next slot
+    // boundary (unless a producer already moved it) and let the caller read on
+    fn corrupt_at(&self, read_idx: u64, what: &'static str) -> Result<(), Error> {
+        if !self.resync_on_corruption {
+            return Err(Error::Corrupt(what));
+        }
+        let slot_size = self.slot_size as u64;
+        let next = (read_idx - read_idx % slot_size).wrapping_add(slot_size);
+        if self.header().read_idx.compare_exchange(read_idx, next, Ordering::Release, Ordering::Relaxed).is_ok() {
+            self.resynced.fetch_add(next.wrapping_sub(read_idx), Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    // Free the slot at `read_idx`, false if a producer already moved past it
+    fn release_slot(&self, read_idx: u64) -> bool {
+        self.debug_check_slot(read_idx);
+        if self.zero_on_recycle {
+            unsafe { ptr::write_bytes(self.buffer.add(self.slot_offset(read_idx)), 0, self.slot_size) };
+        }
+        let next = read_idx.wrapping_add(self.slot_size as u64);
+        self.header().read_idx.compare_exchange(read_idx, next, Ordering::Release, Ordering::Relaxed).is_ok()
+    }
+}
+
+// Layout and indices only: the slots themselves are left alone
+impl fmt::Debug for SharedRingBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = self.header();
+        f.debug_struct("SharedRingBuffer")
+            .field("name", &format_args!("{}", self.name))
+            .field("capacity", &self.capacity())
+            .field("slot_size", &self.slot_size)
+            .field("read_idx", &header.read_idx.load(Ordering::Relaxed))
+            .field("write_idx", &header.write_idx.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for SharedRingBuffer {
+    fn drop(&mut self) {
+        _ = self.teardown();
+    }
+}
+
+// The oldest message of a ring, borrowed from its slot by `read_message_ref`.
+// Dropping it frees the slot.
+struct SlotRef<'a> {
+    ring: &'a SharedRingBuffer,
+    read_idx: u64,
+    message: &'a [u8],
+}
+
+impl SlotRef<'_> {
+    // Free the slot now, false if a producer overwrote it in the meantime (so the
+    // bytes seen through this guard may be torn)
+    fn release(self) -> bool {
+        let released = self.ring.release_slot(self.read_idx);
+        std::mem::forget(self);
+        released
+    }
+}
+
+impl std::ops::Deref for SlotRef<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.message
+    }
+}
+
+impl Drop for SlotRef<'_> {
+    fn drop(&mut self) {
+        self.ring.release_slot(self.read_idx);
+    }
+}
+
+// A slot claimed by `SharedRingBuffer::reserve`, written through `DerefMut` and
+// handed to the consumer by `publish`. Dropping it unpublished leaves the ring as
+// it was, and the next write claims the same slot, which is also why the producer
+// mustn't write anything else while holding one (single producer only).
+struct Reservation<'a> {
+    ring: &'a SharedRingBuffer,
+    write_idx: u64,
+    payload: &'a mut [u8],
+}
+
+impl Reservation<'_> {
+    // Publish the message, advancing `write_idx`
+    fn publish(self) {
+        self.ring.publish_slot(self.write_idx, self.payload.len());
+    }
+}
+
+impl std::ops::Deref for Reservation<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.payload
+    }
+}
+
+impl std::ops::DerefMut for Reservation<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.payload
+    }
+}
+
+// Buffers for `SharedRingBuffer::read_message_pooled` to reuse. Clones share the
+// same buffers, so one pool can serve several consumers (threads included).
+#[derive(Clone)]
+struct BufferPool {
+    free: Arc<Mutex<Vec<Vec<u8>>>>,
+    max_buffers: usize, // Kept for reuse at most; any more are freed on return
+}
+
+impl BufferPool {
+    fn new(max_buffers: usize) -> Self {
+        Self { free: Arc::new(Mutex::new(Vec::with_capacity(max_buffers))), max_buffers }
+    }
+
+    // A free buffer, or a new one if none is left
+    fn take(&self) -> Vec<u8> {
+        self.free.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pop().unwrap_or_default()
+    }
+
+    fn give_back(&self, buf: Vec<u8>) {
+        let mut free = self.free.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if free.len() < self.max_buffers {
+            free.push(buf);
+        }
+    }
+}
+
+// A message read by `read_message_pooled`, whose buffer returns to the pool on drop
+struct PooledBuf {
+    buf: Vec<u8>,
+    pool: BufferPool,
+}
+
+impl std::ops::Deref for PooledBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(feature = "dispatch")]
+type Handler<'a> = Box<dyn FnMut(&[u8]) + 'a>;
+#[cfg(feature = "dispatch")]
+type DefaultHandler<'a> = Box<dyn FnMut(u8, &[u8]) + 'a>; // Gets the tag too
+
+// Per-tag message handlers for `SharedRingBuffer::dispatch`
+#[cfg(feature = "dispatch")]
+struct Handlers<'a> {
+    by_tag: [Option<Handler<'a>>; 256],
+    default: DefaultHandler<'a>,
+}
+
+#[cfg(feature = "dispatch")]
+impl<'a> Handlers<'a> {
+    // Send every tag to `default` until `on` registers a handler for it
+    fn new(default: impl FnMut(u8, &[u8]) + 'a) -> Self {
+        Self { by_tag: std::array::from_fn(|_| None), default: Box::new(default) }
+    }
+
+    // Handle messages tagged `tag` with `handler`, replacing any earlier one
+    fn on(mut self, tag: u8, handler: impl FnMut(&[u8]) + 'a) -> Self {
+        self.by_tag[tag as usize] = Some(Box::new(handler));
+        self
+    }
+}
+
+// Rings of different slot sizes used as one, for traffic where a single slot size
+// would either waste space on small messages or not fit the large ones: each
+// message goes to the class with the smallest slots it fits in. Every message is
+// stamped with a sequence number and `read_message` takes the lowest one waiting,
+// so delivery is in order unless a message lands in a class the consumer has just
+// looked at (FIFO-ish). Each class is its own segment, named after the ring plus
+// its slot size (`/name.128`). Meant for one producer and one consumer, and no
+// full policy that drops or overwrites.
+struct SizeClassRing {
+    classes: Vec<SharedRingBuffer>, // Smallest slots first
+    next_seq: AtomicU64,
+}
+
+impl SizeClassRing {
+    // Create one class per `(capacity, slot_size)` pair
+    fn new(name: &RingName, classes: &[(usize, usize)]) -> Result<Self, Error> {
+        let mut classes = classes.to_vec();
+        classes.sort_by_key(|&(_, slot_size)| slot_size);
+        if classes.is_empty() || classes.windows(2).any(|pair| pair[0].1 == pair[1].1) {
+            return Err(Error::InvalidConfig("size classes need distinct slot sizes"));
+        }
+        if classes[0].1 <= 4 + 8 {
+            return Err(Error::InvalidConfig("slot_size must leave room after the length prefix and sequence number"));
+        }
+
+        let classes = classes
+            .into_iter()
+            .map(|(capacity, slot_size)| {
+                RingConfig::new(&Self::class_name(name, slot_size)?).slot_size(slot_size).capacity(capacity).build()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { classes, next_seq: AtomicU64::new(0) })
+    }
+
+    // Attach to the classes with `slot_sizes` of a ring created by another process
+    fn open(name: &RingName, slot_sizes: &[usize], timeout: Duration) -> Result<Self, Error> {
+        let mut slot_sizes = slot_sizes.to_vec();
+        slot_sizes.sort_unstable();
+        let classes = slot_sizes
+            .into_iter()
+            .map(|slot_size| SharedRingBuffer::open(&Self::class_name(name, slot_size)?, timeout))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { classes, next_seq: AtomicU64::new(0) })
+    }
+
+    fn class_name(name: &RingName, slot_size: usize) -> Result<RingName, Error> {
+        RingName::try_from(format!("{}.{}", name, slot_size).as_str())
+    }
+
+    fn write_message(&self, message: &[u8]) -> Result<usize, Error> {
+        let Some(class) = self.classes.iter().find(|class| message.len() <= class.max_message_len() - 8) else {
+            let largest = &self.classes[self.classes.len() - 1];
+            largest.header().overflow_count.fetch_add(1, Ordering::Relaxed);
+            return Err(Error::MessageTooLarge { len: message.len(), max: largest.max_message_len() - 8 });
+        };
+        let seq = self.next_seq.load(Ordering::Relaxed);
+        let written = class.write_prefixed(&seq.to_le_bytes(), message, None)?;
+        self.next_seq.store(seq + 1, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    // The oldest message across the classes, waiting with the smallest class's
+    // `WaitStrategy` while they are all empty. Like a single ring's `read_message`,
+    // `Error::Poisoned` once the producer is gone and every class is drained, and
+    // `Error::Shutdown` once any class is shut down.
+    fn read_message(&self) -> Result<Vec<u8>, Error> {
+        let mut waiting_since = None;
+        loop {
+            // Before looking for messages, so one written just before poisoning is seen
+            let poisoned = self.classes.iter().any(SharedRingBuffer::is_poisoned);
+            let oldest = self
+                .classes
+                .iter()
+                .filter_map(|class| class.peek_prefix::<8>().map(|seq| (u64::from_le_bytes(seq), class)))
+                .min_by_key(|&(seq, _)| seq);
+            if let Some((_, class)) = oldest {
+                let mut message = class.read_message()?;
+                if message.len() < 8 {
+                    return Err(Error::Corrupt("size class message without its sequence number"));
+                }
+                message.drain(..8);
+                return Ok(message);
+            }
+            if poisoned {
+                return Err(Error::Poisoned);
+            }
+            if self.classes.iter().any(SharedRingBuffer::is_shutdown) {
+                return Err(Error::Shutdown);
+            }
+            let class = &self.classes[0];
+            class.wait.wait(*waiting_since.get_or_insert_with(Instant::now), class.read_spin, None);
+        }
+    }
+}
+
+// Rings used as one by several producer threads, each writing to its own shard
+// (its thread number modulo the shard count, see `thread_number`) so threads
+// don't contend on one `write_idx`. Ordering is per-shard FIFO only: messages
+// from one thread arrive in order, but the consumer takes the shards round-robin,
+// one message at a time, so there's no global order across threads. Threads
+// sharing a shard serialize on its lock, which is why all producers must be in
+// the process that created or opened the ring. Each shard is its own segment,
+// named after the ring plus its index (`/name.shard0`); one consumer.
+struct ShardedRing {
+    shards: Vec<SharedRingBuffer>,
+    write_locks: Vec<Mutex<()>>, // One per shard, only contended by threads sharing it
+    next_read: AtomicUsize,      // Shard `read_message` looks at first
+}
+
+impl ShardedRing {
+    // Create `shards` shards of `capacity` slots each
+    fn new(name: &RingName, shards: usize, capacity: usize) -> Result<Self, Error> {
+        if shards == 0 {
+            return Err(Error::InvalidConfig("a sharded ring needs at least one shard"));
+        }
+        let shards = (0..shards)
+            .map(|shard| RingConfig::new(&Self::shard_name(name, shard)?).capacity(capacity).build())
+            .collect::<Result<_, _>>()?;
+        Ok(Self::from_shards(shards))
+    }
+
+    // Attach to the `shards` shards of a ring created by another process
+    fn open(name: &RingName, shards: usize, timeout: Duration) -> Result<Self, Error> {
+        if shards == 0 {
+            return Err(Error::InvalidConfig("a sharded ring needs at least one shard"));
+        }
+        let shards = (0..shards)
+            .map(|shard| SharedRingBuffer::open(&Self::shard_name(name, shard)?, timeout))
+            .collect::<Result<_, _>>()?;
+        Ok(Self::from_shards(shards))
+    }
+
+    fn from_shards(shards: Vec<SharedRingBuffer>) -> Self {
+        let write_locks = shards.iter().map(|_| Mutex::new(())).collect();
+        Self { shards, write_locks, next_read: AtomicUsize::new(0) }
+    }
+
+    fn shard_name(name: &RingName, shard: usize) -> Result<RingName, Error> {
+        RingName::try_from(format!("{}.shard{}", name, shard).as_str())
+    }
+
+    // The calling thread's number, handed out in the order threads first write
+    // to any `ShardedRing`, so the first `shards` writers get a shard each
+    fn thread_number() -> usize {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        thread_local! {
+            static NUMBER: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+        }
+        NUMBER.with(|number| *number)
+    }
+
+    // `write_message` to the calling thread's shard
+    fn write_message(&self, message: &[u8]) -> Result<usize, Error> {
+        let shard = Self::thread_number() % self.shards.len();
+        let _guard = self.write_locks[shard].lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.shards[shard].write_message(message)
+    }
+
+    // The next message of the first non-empty shard after the one last read from,
+    // waiting (with the first shard's `WaitStrategy`) if they are all empty.
+    // `Error::Poisoned` once every shard is poisoned and drained, `Error::Shutdown`
+    // once any shard is shut down.
+    fn read_message(&self) -> Result<Vec<u8>, Error> {
+        let mut waiting_since = None;
+        loop {
+            // Before looking for messages, so one written just before poisoning is seen
+            let poisoned = self.shards.iter().all(SharedRingBuffer::is_poisoned);
+            let first = self.next_read.load(Ordering::Relaxed);
+            for offset in 0..self.shards.len() {
+                let shard = (first + offset) % self.shards.len();
+                if !self.shards[shard].is_empty() {
+                    self.next_read.store((shard + 1) % self.shards.len(), Ordering::Relaxed);
+                    return self.shards[shard].read_message();
+                }
+            }
+            if poisoned {
+                return Err(Error::Poisoned);
+            }
+            if self.shards.iter().any(SharedRingBuffer::is_shutdown) {
+                return Err(Error::Shutdown);
+            }
+            let shard = &self.shards[0];
+            shard.wait.wait(*waiting_since.get_or_insert_with(Instant::now), shard.read_spin, None);
+        }
+    }
+}
+
+// Map the control page (of a `RingPair` or `ChainedRing`) behind `fd`, closing it
+fn map_control_page<T>(fd: libc::c_int) -> Result<*mut T, Error> {
+    let addr = unsafe { mmap(ptr::null_mut(), PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0) };
+    let err = io::Error::last_os_error();
+    unsafe { close(fd) };
+    if addr == libc::MAP_FAILED {
+        return Err(Error::os("Failed to map shared memory", err));
+    }
+    Ok(addr as *mut T)
+}
+
+// Control page of a `RingPair`
+#[repr(C)]
+struct PairControl {
+    initialized: AtomicU32,
+    active: AtomicU64,         // Generation; the active ring is `active % 2`
+    writers: [AtomicU64; 2],   // Producers between announcing themselves and publishing, per ring
+}
+
+const _: () = assert!(std::mem::size_of::<PairControl>() <= PAGE_SIZE);
+
+// Two rings used as one for a consumer that works in generations: the producer
+// writes to the active ring, and `swap` makes the other one active, so the producer
+// moves on to a fresh ring while the consumer drains the one it left (its retired
+// ring) at leisure. Which ring is active lives in a control page of its own
+// (`/name`, the rings being `/name.0` and `/name.1`), as a generation number whose
+// parity picks the ring.
+//
+// Handoff: the producer announces itself in the count of writers to the ring it is
+// about to write to, then checks that ring is still active, and leaves the count
+// once its message is published. `swap` bumps the generation first, so a producer
+// either sees the new one or is already counted in the old ring; once
+// `read_retired` finds the retired ring empty with no writers left in it, that
+// generation is complete and it returns `None`. Drain the retired ring before the
+// next `swap`, which hands it back to the producer. One producer, one consumer,
+// and a producer that dies mid-write leaves the retired ring never complete.
+struct RingPair {
+    control: *mut PairControl,
+    rings: [SharedRingBuffer; 2],
+    name: RingName,
+    owner: bool,
+    owner_lock: Option<OwnedFd>, // Held by the creator, see `create_segment`
+}
+
+unsafe impl Send for RingPair {}
+unsafe impl Sync for RingPair {}
+
+impl RingPair {
+    // Create both rings, of `capacity` slots each, with the first one active
+    fn new(name: &RingName, capacity: usize) -> Result<Self, Error> {
+        let control_name = name.clone();
+        let (fd, owner_lock) = create_segment(&control_name, PAGE_SIZE, false)?;
+        let control = map_control_page(fd).inspect_err(|_| unsafe {
+            shm_unlink(control_name.0.as_ptr());
+        })?;
+        // Before the rings, so an opener that finds them finds the control page too
+        let rings = (|| {
+            Ok::<_, Error>([
+                RingConfig::new(&Self::ring_name(name, 0)?).capacity(capacity).build()?,
+                RingConfig::new(&Self::ring_name(name, 1)?).capacity(capacity).build()?,
+            ])
+        })();
+        let rings = rings.inspect_err(|_| unsafe {
+            munmap(control as *mut libc::c_void, PAGE_SIZE);
+            shm_unlink(control_name.0.as_ptr());
+        })?;
+        let pair = Self { control, rings, name: control_name, owner: true, owner_lock };
+        pair.control().active.store(0, Ordering::Relaxed);
+        pair.control().writers.iter().for_each(|writers| writers.store(0, Ordering::Relaxed));
+        pair.control().initialized.store(INITIALIZED, Ordering::Release);
+        Ok(pair)
+    }
+
+    // Attach to a pair created by another process
+    fn open(name: &RingName, timeout: Duration) -> Result<Self, Error> {
+        let deadline = Instant::now() + timeout;
+        let rings = [
+            SharedRingBuffer::open(&Self::ring_name(name, 0)?, timeout)?,
+            SharedRingBuffer::open(&Self::ring_name(name, 1)?, timeout)?,
+        ];
+        let control_name = name.clone();
+        let fd = open_segment(&control_name, O_RDWR)?;
+        if wait_for_size(fd, deadline)? != PAGE_SIZE {
+            unsafe { close(fd) };
+            return Err(Error::InvalidConfig("not the control page of a ring pair"));
+        }
+        let pair = Self { control: map_control_page(fd)?, rings, name: control_name, owner: false, owner_lock: None };
+        while pair.control().initialized.load(Ordering::Acquire) != INITIALIZED {
+            if Instant::now() >= deadline {
+                return Err(Error::TimedOut);
+            }
+            spin_wait(Duration::from_micros(100));
+        }
+        Ok(pair)
+    }
+
+    fn ring_name(name: &RingName, ring: usize) -> Result<RingName, Error> {
+        RingName::try_from(format!("{}.{}", name, ring).as_str())
+    }
+
+    #[inline(always)]
+    fn control(&self) -> &PairControl {
+        unsafe { &*self.control }
+    }
+
+    // The ring the producer writes to
+    fn active(&self) -> &SharedRingBuffer {
+        &self.rings[(self.control().active.load(Ordering::Acquire) % 2) as usize]
+    }
+
+    // `write_message` to the active ring, see the handoff above
+    fn write_message(&self, message: &[u8]) -> Result<usize, Error> {
+        let control = self.control();
+        let ring = loop {
+            let generation = control.active.load(Ordering::SeqCst);
+            let ring = (generation % 2) as usize;
+            control.writers[ring].fetch_add(1, Ordering::SeqCst);
+            if control.active.load(Ordering::SeqCst) == generation {
+                break ring;
+            }
+            // Swapped in between, announce ourselves to the new one instead
+            control.writers[ring].fetch_sub(1, Ordering::Release);
+        };
+        let written = self.rings[ring].write_message(message);
+        control.writers[ring].fetch_sub(1, Ordering::Release);
+        written
+    }
+
+    // Make the other ring active and return the one just retired, for `read_retired`
+    fn swap(&self) -> &SharedRingBuffer {
+        let retired = self.control().active.fetch_add(1, Ordering::SeqCst) % 2;
+        &self.rings[retired as usize]
+    }
+
+    // The next message of the retired ring, waiting for a producer still writing to
+    // it, or `None` once its generation is complete
+    fn read_retired(&self) -> Result<Option<Vec<u8>>, Error> {
+        let control = self.control();
+        let ring = ((control.active.load(Ordering::Acquire) + 1) % 2) as usize;
+        loop {
+            if !self.rings[ring].is_empty() {
+                return self.rings[ring].read_message().map(Some);
+            }
+            // A producer leaves the count only after publishing, so look again
+            if control.writers[ring].load(Ordering::SeqCst) == 0 {
+                if !self.rings[ring].is_empty() {
+                    continue;
+                }
+                return Ok(None);
+            }
+            spin_wait(Duration::from_micros(1));
+        }
+    }
+}
+
+impl Drop for RingPair {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.control as *mut libc::c_void, PAGE_SIZE);
+            if self.owner {
+                shm_unlink(self.name.0.as_ptr());
+            }
+        }
+    }
+}
+
+// Control page of a `ChainedRing`
+#[repr(C)]
+struct ChainControl {
+    initialized: AtomicU32,
+    head: AtomicU64, // Segment the producer writes to
+    tail: AtomicU64, // Segment the consumer reads from
+}
+
+const _: () = assert!(std::mem::size_of::<ChainControl>() <= PAGE_SIZE);
+
+// A ring that grows instead of filling up, for bursty traffic (e.g. logging) that
+// shouldn't be dropped or stall the producer: a chain of ordinary rings (`/name.0`,
+// `/name.1`, ...) of `capacity` slots each, plus a control page (`/name`) recording
+// the segment the producer writes to (`head`) and the one the consumer reads
+// (`tail`). When the head segment is full the producer creates the next one,
+// writes there and moves `head` to it, never to write to the old one again. At most
+// `max_segments` exist at once; past that `write_message` waits on the head
+// segment like a plain ring.
+//
+// Ordering: the consumer reads a segment until it is empty with `head` moved past
+// it (the producer's last write to it came before the move), then opens the next
+// and moves `tail` to it, so messages arrive in the order written across segments.
+// Cleanup: the consumer drops its handle on a segment it is done with; the producer
+// drops (and so removes) every segment behind `tail` on its next write, and an idle
+// producer keeps them until then. One producer and one consumer, each with its own
+// `ChainedRing`; dropping the producer's removes what is left of the chain.
+struct ChainedRing {
+    control: *mut ChainControl,
+    name: RingName, // Of the control page, the segments being `name.0`, `name.1`, ...
+    capacity: usize, // Slots per segment
+    max_segments: u64,
+    segments: Vec<(u64, SharedRingBuffer)>, // By number: the producer's `tail..=head`, the consumer's one
+    owner: bool,
+    owner_lock: Option<OwnedFd>, // Held by the creator, see `create_segment`
+}
+
+unsafe impl Send for ChainedRing {}
+unsafe impl Sync for ChainedRing {}
+
+impl ChainedRing {
+    // Create the chain with its first segment, for the producer
+    fn create(name: &RingName, capacity: usize, max_segments: usize) -> Result<Self, Error> {
+        let control_name = name.clone();
+        let (fd, owner_lock) = create_segment(&control_name, PAGE_SIZE, false)?;
+        let control = map_control_page::<ChainControl>(fd).inspect_err(|_| unsafe {
+            shm_unlink(control_name.0.as_ptr());
+        })?;
+        let first = RingConfig::new(&Self::segment_name(name, 0)?).capacity(capacity).build();
+        let first = first.inspect_err(|_| unsafe {
+            munmap(control as *mut libc::c_void, PAGE_SIZE);
+            shm_unlink(control_name.0.as_ptr());
+        })?;
+        let chain = Self {
+            control,
+            name: control_name,
+            capacity,
+            max_segments: max_segments.max(1) as u64,
+            segments: vec![(0, first)],
+            owner: true,
+            owner_lock,
+        };
+        chain.control().head.store(0, Ordering::Relaxed);
+        chain.control().tail.store(0, Ordering::Relaxed);
+        chain.control().initialized.store(INITIALIZED, Ordering::Release);
+        Ok(chain)
+    }
+
+    // Attach to a chain created by another process, for the consumer, at the segment
+    // the last consumer left off
+    fn open(name: &RingName, timeout: Duration) -> Result<Self, Error> {
+        let deadline = Instant::now() + timeout;
+        let control_name = name.clone();
+        let fd = open_segment(&control_name, O_RDWR)?;
+        if wait_for_size(fd, deadline)? != PAGE_SIZE {
+            unsafe { close(fd) };
+            return Err(Error::InvalidConfig("not the control page of a chained ring"));
+        }
+        let control = map_control_page::<ChainControl>(fd)?;
+        let mut chain = Self {
+            control,
+            name: control_name,
+            capacity: 0,
+            max_segments: 0,
+            segments: Vec::new(),
+            owner: false,
+            owner_lock: None,
+        };
+        while chain.control().initialized.load(Ordering::Acquire) != INITIALIZED {
+            if Instant::now() >= deadline {
+                return Err(Error::TimedOut);
+            }
+            spin_wait(Duration::from_micros(100));
+        }
+        let tail = chain.control().tail.load(Ordering::Acquire);
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let ring = SharedRingBuffer::open(&Self::segment_name(name, tail)?, remaining)?;
+        chain.capacity = ring.capacity();
+        chain.segments.push((tail, ring));
+        Ok(chain)
+    }
+
+    fn segment_name(name: &RingName, segment: u64) -> Result<RingName, Error> {
+        RingName::try_from(format!("{}.{}", name, segment).as_str())
+    }
+
+    #[inline(always)]
+    fn control(&self) -> &ChainControl {
+        unsafe { &*self.control }
+    }
+
+    // `write_message` to the head segment, chaining a new one if it is full
+    fn write_message(&mut self, message: &[u8]) -> Result<usize, Error> {
+        let tail = self.control().tail.load(Ordering::Acquire);
+        self.segments.retain(|&(segment, _)| segment >= tail);
+        let (head, ring) = self.segments.last().expect("the producer always holds its head segment");
+        let head = *head;
+        // A message no segment takes gets its error from the head one
+        if !ring.is_full() || message.len() > ring.max_message_len() || head - tail + 1 >= self.max_segments {
+            return ring.write_message(message);
+        }
+
+        let next = RingConfig::new(&Self::segment_name(&self.name, head + 1)?).capacity(self.capacity).build()?;
+        let written = next.write_message(message);
+        self.segments.push((head + 1, next));
+        self.control().head.store(head + 1, Ordering::Release);
+        written
+    }
+
+    // The next message, following the chain into the next segment once the
+    // current one is finished, waiting if there is none yet
+    fn read_message(&mut self) -> Result<Vec<u8>, Error> {
+        loop {
+            let (segment, ring) = self.segments.first().expect("the consumer always holds its segment");
+            let segment = *segment;
+            if !ring.is_empty() {
+                return ring.read_message();
+            }
+            if self.control().head.load(Ordering::Acquire) > segment {
+                // The producer is done with this segment, but may have filled it
+                // between the two looks
+                if !ring.is_empty() {
+                    continue;
+                }
+                // Created before `head` moved, so it's there already
+                let next_name = Self::segment_name(&self.name, segment + 1)?;
+                let next = SharedRingBuffer::open(&next_name, Duration::from_secs(1))?;
+                self.segments = vec![(segment + 1, next)];
+                self.control().tail.store(segment + 1, Ordering::Release);
+                continue;
+            }
+            spin_wait(Duration::from_micros(1));
+        }
+    }
+}
+
+impl Drop for ChainedRing {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.control as *mut libc::c_void, PAGE_SIZE);
+            if self.owner {
+                shm_unlink(self.name.0.as_ptr());
+            }
+        }
+    }
+}
+
+// `std::sync::mpsc`-style handles on a ring created from `config`: clone the
+// `Sender` for more producers, keep the one `Receiver` on the consuming thread.
+// Both ends live in this process; other processes attach to the ring by name as
+// usual (as a single producer, since the senders only serialize among themselves).
+fn channel(config: RingConfig) -> Result<(Sender, Receiver), Error> {
+    let ring = Arc::new(config.build()?);
+    let sender = Sender { ring: Arc::clone(&ring), write_lock: Arc::new(Mutex::new(())) };
+    Ok((sender, Receiver { ring, _not_sync: std::marker::PhantomData }))
+}
+
+// Producing end of a `channel`
+#[derive(Clone)]
+struct Sender {
+    ring: Arc<SharedRingBuffer>,
+    write_lock: Arc<Mutex<()>>, // `write_message` is single-producer, so clones take turns
+}
+
+impl Sender {
+    // `write_message`, so it waits on a full ring as the ring's `FullPolicy` says
+    fn send(&self, message: &[u8]) -> Result<usize, Error> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.ring.write_message(message)
+    }
+
+    // Bridge a `crossbeam_channel` into the ring: a thread `send`s every message
+    // that arrives on `messages` until all its senders are gone (`Ok`) or a write
+    // fails (that error), so channel-based code can feed the ring unchanged. The cost
+    // is one thread per bridge and a hop through the channel per message (an
+    // allocation on the channel side and a thread handoff, microseconds rather than
+    // the ring's nanoseconds), so latency-critical producers should write directly.
+    // The ring isn't marked done at the end; other `Sender`s may still be writing.
+    #[cfg(feature = "crossbeam")]
+    fn forward_from(&self, messages: crossbeam_channel::Receiver<Vec<u8>>) -> thread::JoinHandle<Result<(), Error>> {
+        let sender = self.clone();
+        thread::spawn(move || {
+            for message in messages {
+                sender.send(&message)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// Consuming end of a `channel`: `Send` but not `Sync` or `Clone`, so there's only
+// ever one reader
+struct Receiver {
+    ring: Arc<SharedRingBuffer>,
+    _not_sync: std::marker::PhantomData<std::cell::Cell<()>>,
+}
+
+impl Receiver {
+    // Wait for the next message
+    fn recv(&self) -> Result<Vec<u8>, Error> {
+        self.ring.read_message()
+    }
+
+    // The next message, or `None` if the ring is empty right now
+    fn try_recv(&self) -> Result<Option<Vec<u8>>, Error> {
+        if self.ring.is_empty() {
+            return Ok(None);
+        }
+        self.ring.read_message().map(Some)
+    }
+
+    // Bridge the ring into a `crossbeam_channel`: a thread hands every message to
+    // `messages` until the producer is done and the ring drained, or the channel's
+    // receivers are all gone (`Ok` either way), or a read fails (that error). A
+    // dropped channel is only noticed when the next message comes in. Same overhead
+    // as `Sender::forward_from`, plus the thread waiting on the ring per its
+    // `WaitStrategy`, which with the default `Spin` keeps a core busy.
+    #[cfg(feature = "crossbeam")]
+    fn forward_to(self, messages: crossbeam_channel::Sender<Vec<u8>>) -> thread::JoinHandle<Result<(), Error>> {
+        thread::spawn(move || loop {
+            // Returns 0 only once the producer is done and nothing is left
+            if self.ring.wait_readable(1, Duration::MAX)? == 0 {
+                return Ok(());
+            }
+            if messages.send(self.ring.read_message()?).is_err() {
+                return Ok(());
+            }
+        })
+    }
+}
+
+// Read-only view of a ring for out-of-band monitoring. The segment is mapped with
+// `O_RDONLY` and `PROT_READ`, so the observer can't corrupt it, and it keeps a cursor
+// of its own: the consumer never waits for it and never notices it. Messages are
+// seen from where the consumer was at `open_readonly`; an observer that falls
+// behind the consumer skips ahead, since those slots may already be reused.
+// Split and zero-on-recycle rings aren't supported.
+struct RingObserver {
+    header: *const Header,
+    buffer: *const u8,
+    buffer_size: usize,
+    slot_size: usize,
+    endianness: Endianness,
+    framing: Framing,
+    cursor: u64, // Like `read_idx`, but private to this observer
+    epoch: u64,
+}
+
+unsafe impl Send for RingObserver {}
+unsafe impl Sync for RingObserver {}
+
+impl RingObserver {
+    // Attach read-only to `name`, waiting up to `timeout` for its creator
+    fn open_readonly(name: &RingName, timeout: Duration) -> Result<Self, Error> {
+        let deadline = Instant::now() + timeout;
+        let fd = open_segment(name, O_RDONLY)?;
+        let segment_size = wait_for_size(fd, deadline)?;
+        if segment_size <= DATA_OFFSET {
+            unsafe { close(fd) };
+            return Err(Error::InvalidConfig("segment has no data region, split rings can't be observed"));
+        }
+        let addr = unsafe { mmap(ptr::null_mut(), segment_size, PROT_READ, MAP_SHARED, fd, 0) };
+        let err = io::Error::last_os_error();
+        unsafe { close(fd) };
+        if addr == libc::MAP_FAILED {
+            return Err(Error::os("Failed to map shared memory", err));
+        }
+
+        // Dropping `observer` unmaps the segment from here on
+        let mut observer = Self {
+            header: addr as *const Header,
+            buffer: unsafe { (addr as *const u8).add(DATA_OFFSET) },
+            buffer_size: segment_size - DATA_OFFSET,
+            slot_size: SLOT_SIZE,
+            endianness: Endianness::Little,
+            framing: Framing::default(),
+            cursor: 0,
+            epoch: 0,
+        };
+        let header = observer.header();
+        while header.initialized.load(Ordering::Acquire) != INITIALIZED {
+            if Instant::now() >= deadline {
+                return Err(Error::TimedOut);
+            }
+            spin_wait(Duration::from_micros(100));
+        }
+        let endianness = Endianness::from_header(header.endianness.load(Ordering::Relaxed))?;
+        let slot_size = header.slot_size.load(Ordering::Relaxed);
+        let framing = Framing::from_header(header.framing.load(Ordering::Relaxed));
+        if header.buffer_size.load(Ordering::Relaxed) != observer.buffer_size {
+            return Err(Error::Corrupt("segment size doesn't match the layout in its header"));
+        }
+        if header.zero_on_recycle.load(Ordering::Relaxed) != 0 {
+            return Err(Error::InvalidConfig("zero-on-recycle rings can't be observed"));
+        }
+        check_layout(observer.buffer_size, slot_size)?;
+        framing.check(slot_size)?;
+        let cursor = header.read_idx.load(Ordering::Acquire);
+        let epoch = header.epoch.load(Ordering::Relaxed);
+        observer.endianness = endianness;
+        observer.slot_size = slot_size;
+        observer.framing = framing;
+        observer.cursor = cursor;
+        observer.epoch = epoch;
+        Ok(observer)
+    }
+
+    #[inline(always)]
+    fn header(&self) -> &Header {
+        unsafe { &*self.header }
+    }
+
+    // Sequence number of the next message this observer will see
+    fn cursor(&self) -> u64 {
+        self.cursor.max(self.header().read_idx.load(Ordering::Acquire)) / self.slot_size as u64
+    }
+
+    // Messages written that this observer hasn't seen yet (and the consumer hasn't read)
+    fn pending(&self) -> usize {
+        let next = self.header().write_idx.load(Ordering::Acquire) / self.slot_size as u64;
+        next.saturating_sub(self.cursor()) as usize
+    }
+
+    // `SharedRingBuffer::last_heartbeat`, for liveness monitoring from outside
+    fn last_heartbeat(&self) -> Option<Instant> {
+        heartbeat_instant(self.header().heartbeat.load(Ordering::Acquire))
+    }
+
+    // `SharedRingBuffer::high_water_mark`
+    fn high_water_mark(&self) -> usize {
+        (self.header().max_occupancy.load(Ordering::Relaxed) as usize).min(self.buffer_size / self.slot_size)
+    }
+
+    // Copy of the next message, or `None` if the observer has caught up with the
+    // producer. Never blocks and never moves the ring's own indices.
+    fn try_read(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        if self.header().epoch.load(Ordering::Relaxed) != self.epoch {
+            return Err(Error::Stale);
+        }
+        loop {
+            let write_idx = self.header().write_idx.load(Ordering::Acquire);
+            self.cursor = self.cursor.max(self.header().read_idx.load(Ordering::Acquire));
+            if self.cursor >= write_idx {
+                return Ok(None);
+            }
+
+            let start = (self.cursor % self.buffer_size as u64) as usize;
+            if start + self.slot_size > self.buffer_size {
+                return Err(Error::Corrupt("slot runs past the end of the buffer"));
+            }
+            let slot = unsafe { self.buffer.add(start) };
+            let (offset, message_len) = unsafe { self.framing.read_len(slot, self.endianness) };
+            if self.framing.tombstone() == Some(message_len) {
+                self.cursor += self.slot_size as u64;
+                continue;
+            }
+            if message_len > self.slot_size - offset {
+                if self.header().read_idx.load(Ordering::Acquire) > self.cursor {
+                    continue;
+                }
+                return Err(Error::Corrupt("message length exceeds slot"));
+            }
+
+            let mut message = Vec::with_capacity(message_len);
+            unsafe {
+                ptr::copy_nonoverlapping(slot.add(offset), message.as_mut_ptr(), message_len);
+                message.set_len(message_len);
+            }
+
+            // Once the consumer is past the slot the producer may be rewriting it, so
+            // the copy only counts if it still wasn't after copying
+            std::sync::atomic::fence(Ordering::Acquire);
+            if self.header().read_idx.load(Ordering::Relaxed) > self.cursor {
+                continue;
+            }
+            self.cursor += self.slot_size as u64;
+            return Ok(Some(message));
+        }
+    }
+}
+
+impl Drop for RingObserver {
+    fn drop(&mut self) {
+        unsafe { munmap(self.header as *mut libc::c_void, DATA_OFFSET + self.buffer_size) };
+    }
+}
+
+// A ring that can pass a file descriptor along with a message. An fd means nothing
+// in another process's memory, so it goes over a Unix datagram socket next to the
+// ring (`SCM_RIGHTS`), and each message carries an 8-byte tag naming the fd sent
+// for it (0 for none). Setup: the consumer `bind`s a socket path before the
+// producer `connect`s to it, and from then on every message on the ring goes
+// through this wrapper. An fd whose message never arrives (the write failed, or
+// `FullPolicy::Overwrite` discarded it) is closed when the consumer moves past it.
+struct HandleRing {
+    ring: SharedRingBuffer,
+    socket: UnixDatagram,
+    next_tag: AtomicU64,
+}
+
+impl HandleRing {
+    // Consumer side: receive fds on a new socket at `path`, replacing a stale socket
+    // left there by an earlier consumer. Anything else at `path` is left alone, and
+    // `bind` then fails on it.
+    fn bind(ring: SharedRingBuffer, path: &Path) -> Result<Self, Error> {
+        if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(path).map_err(|e| Error::Os("Failed to remove stale handle socket", e))?;
+        }
+        let socket = UnixDatagram::bind(path).map_err(|e| Error::Os("Failed to bind handle socket", e))?;
+        Ok(Self { ring, socket, next_tag: AtomicU64::new(1) })
+    }
+
+    // Producer side: send fds to the consumer bound at `path`
+    fn connect(ring: SharedRingBuffer, path: &Path) -> Result<Self, Error> {
+        let socket = UnixDatagram::unbound().map_err(|e| Error::Os("Failed to create handle socket", e))?;
+        socket.connect(path).map_err(|e| Error::Os("Failed to connect handle socket", e))?;
+        Ok(Self { ring, socket, next_tag: AtomicU64::new(1) })
+    }
+
+    // Write `message` with no fd
+    fn write_message(&self, message: &[u8]) -> Result<usize, Error> {
+        self.ring.write_prefixed(&0u64.to_ne_bytes(), message, None)
+    }
+
+    // Write `message` with `fd` alongside it. The kernel duplicates the fd on send,
+    // so the caller still owns (and may close) its own.
+    fn write_message_with_handle(&self, message: &[u8], fd: BorrowedFd<'_>) -> Result<usize, Error> {
+        let tag = self.next_tag.fetch_add(1, Ordering::Relaxed);
+        send_fd(&self.socket, tag, fd.as_raw_fd())?;
+        self.ring.write_prefixed(&tag.to_ne_bytes(), message, None)
+    }
+
+    // The next message and the fd sent with it, if any
+    fn read_message_with_handle(&self) -> Result<(Vec<u8>, Option<OwnedFd>), Error> {
+        let (tag, message) = loop {
+            let slot = self.ring.read_slot()?;
+            let Some((tag, message)) = slot.split_first_chunk::<8>() else {
+                return Err(Error::Corrupt("handle message without its tag"));
+            };
+            let tagged = (u64::from_ne_bytes(*tag), message.to_vec());
+            if slot.release() {
+                break tagged;
+            }
+        };
+        if tag == 0 {
+            return Ok((message, None));
+        }
+        // The fd was sent before its message was written, so it's already queued
+        loop {
+            let (sent_for, fd) = recv_fd(&self.socket)?;
+            match sent_for.cmp(&tag) {
+                std::cmp::Ordering::Less => drop(fd),
+                std::cmp::Ordering::Equal => return Ok((message, Some(fd))),
+                std::cmp::Ordering::Greater => return Err(Error::Corrupt("fd for this message is missing")),
+            }
+        }
+    }
+}
+
+// Send `fd` on `socket` in a datagram holding `tag`
+fn send_fd(socket: &UnixDatagram, tag: u64, fd: RawFd) -> Result<(), Error> {
+    let payload = tag.to_ne_bytes();
+    let mut iov = libc::iovec { iov_base: payload.as_ptr() as *mut libc::c_void, iov_len: payload.len() };
+    let mut control = [0u64; 4];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    let sent = unsafe {
+        msg.msg_controllen = libc::CMSG_SPACE(size_of::<RawFd>() as u32) as _;
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>(), fd);
+        libc::sendmsg(socket.as_raw_fd(), &msg, 0)
+    };
+    if sent < 0 {
+        return Err(Error::Os("Failed to send fd", io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+// Wait for the next datagram from `send_fd`, as `(tag, fd)`
+fn recv_fd(socket: &UnixDatagram) -> Result<(u64, OwnedFd), Error> {
+    let mut payload = [0u8; 8];
+    let mut iov = libc::iovec { iov_base: payload.as_mut_ptr().cast(), iov_len: payload.len() };
+    let mut control = [0u64; 4];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = size_of_val(&control) as _;
+    let received = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+    if received < 0 {
+        return Err(Error::Os("Failed to receive fd", io::Error::last_os_error()));
+    }
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if cmsg.is_null() || (*cmsg).cmsg_level != libc::SOL_SOCKET || (*cmsg).cmsg_type != libc::SCM_RIGHTS {
+            return Err(Error::Corrupt("handle socket datagram without an fd"));
+        }
+        let fd = OwnedFd::from_raw_fd(ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>()));
+        if received as usize != payload.len() {
+            return Err(Error::Corrupt("handle socket datagram without its tag"));
+        }
+        Ok((u64::from_ne_bytes(payload), fd))
+    }
+}
+
+// A fresh `Header::epoch`: wall-clock nanoseconds mixed with the process id, so
+// two creations of the same segment never get the same one by accident
+fn session_epoch() -> u64 {
+    let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |t| t.as_nanos() as u64);
+    nanos ^ ((std::process::id() as u64) << 32)
+}
+
+// Nanoseconds on a clock every process on the host shares and that never goes
+// back (`CLOCK_MONOTONIC`), for `Header::heartbeat`. Never 0.
+fn monotonic_nanos() -> u64 {
+    let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    (now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64).max(1)
+}
+
+// A `Header::heartbeat` stamp as an `Instant` of this process
+fn heartbeat_instant(stamp: u64) -> Option<Instant> {
+    if stamp == 0 {
+        return None;
+    }
+    Instant::now().checked_sub(Duration::from_nanos(monotonic_nanos().saturating_sub(stamp)))
+}
+
+// Why a ring of `slot_size` slots framed with `framing` can't be used as a table
+// (see `write_slot_at`), if it can't. Any layout is fine as a queue, so this is
+// only reported by the table calls.
+fn table_layout(slot_size: usize, framing: Framing) -> Result<(), &'static str> {
+    if !slot_size.is_multiple_of(8) {
+        return Err("table slots need a slot_size that is a multiple of 8");
+    }
+    if slot_size < 8 + framing.overhead() {
+        return Err("table slots need room for the version and the length prefix");
+    }
+    Ok(())
+}
+
+// Reject layouts the index scheme can't handle
+fn check_layout(buffer_size: usize, slot_size: usize) -> Result<(), Error> {
+    if slot_size <= 4 {
+        return Err(Error::InvalidConfig("slot_size must leave room after the 4 byte length prefix"));
+    }
+    if !buffer_size.is_multiple_of(slot_size) {
+        return Err(Error::InvalidConfig("slot_size must divide buffer_size"));
+    }
+    // No slot at all leaves the ring full forever, and one would have every write
+    // wait for the read before it
+    if buffer_size / slot_size < 2 {
+        return Err(Error::InvalidConfig("buffer_size must hold at least two slots"));
+    }
+    if cfg!(feature = "magic-ring") && !buffer_size.is_multiple_of(DATA_OFFSET) {
+        return Err(Error::InvalidConfig("buffer_size must be a multiple of the page size to be mirrored"));
+    }
+    Ok(())
+}
+
+// shm_open a new segment `size` bytes long, along with the creator's lock on it: an
+// exclusive `flock`, held through a dup of the fd for as long as the creating
+// handle lives. While that owner is alive another `create` fails with `EEXIST`
+// before resizing anything, instead of re-initializing the header under it,
+// however the two race; a crashed owner's lock goes with its process, so its stale
+// segment can still be taken over. Where shared memory can't be locked there's no
+// lock and no such check.
+fn create_segment(name: &RingName, size: usize, exclusive: bool) -> Result<(libc::c_int, Option<OwnedFd>), Error> {
+    let oflag = if exclusive { O_CREAT | O_EXCL | O_RDWR } else { O_CREAT | O_RDWR };
+    let fd = unsafe { shm_open(name.0.as_ptr(), oflag, 0o666) };
+    if fd == -1 {
+        return Err(Error::os("Failed to create shared memory", io::Error::last_os_error()));
+    }
+    claim_segment(fd, size)
+}
+
+// `create_segment` for a ring in the file at `path` (`RingConfig::backing_file`)
+fn create_file(path: &Path, size: usize, exclusive: bool) -> Result<(libc::c_int, Option<OwnedFd>), Error> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false) // Sized once locked, by `claim_segment`
+        .create_new(exclusive)
+        .open(path)
+        .map_err(|e| Error::os("Failed to create ring file", e))?;
+    claim_segment(file.into_raw_fd(), size)
+}
+
+// The creator's half of `create_segment` for the freshly opened `fd`: its lock, then
+// the size
+fn claim_segment(fd: libc::c_int, size: usize) -> Result<(libc::c_int, Option<OwnedFd>), Error> {
+    let lock = if unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        let lock_fd = unsafe { libc::dup(fd) };
+        if lock_fd == -1 {
+            let err = io::Error::last_os_error();
+            unsafe { close(fd) };
+            return Err(Error::os("Failed to hold the shared memory lock", err));
+        }
+        Some(unsafe { OwnedFd::from_raw_fd(lock_fd) })
+    } else if io::Error::last_os_error().raw_os_error() == Some(libc::EWOULDBLOCK) {
+        unsafe { close(fd) };
+        return Err(Error::Os("Shared memory is owned by a live process", io::Error::from_raw_os_error(libc::EEXIST)));
+    } else {
+        None
+    };
+
+    if unsafe { ftruncate(fd, size as i64) } == -1 {
+        let err = io::Error::last_os_error();
+        unsafe { close(fd) };
+        return Err(Error::os("Failed to size shared memory", err));
+    }
+    Ok((fd, lock))
+}
+
+// The ring file at `path`, for `open_single`
+fn open_file(path: &Path) -> Result<libc::c_int, Error> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| Error::os("Failed to open ring file", e))?;
+    Ok(file.into_raw_fd())
+}
+
+fn open_segment(name: &RingName, oflag: libc::c_int) -> Result<libc::c_int, Error> {
+    let fd = unsafe { shm_open(name.0.as_ptr(), oflag, 0) };
+    if fd == -1 {
+        return Err(Error::os("Failed to open shared memory", io::Error::last_os_error()));
+    }
+    Ok(fd)
+}
+
+// Size of the segment behind `fd` once its creator has set it, closing `fd` on
+// error. Touching the mapping before the creator's ftruncate would SIGBUS.
+fn wait_for_size(fd: libc::c_int, deadline: Instant) -> Result<usize, Error> {
+    loop {
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { fstat(fd, &mut stat) } == -1 {
+            let err = io::Error::last_os_error();
+            unsafe { close(fd) };
+            return Err(Error::os("Failed to stat shared memory", err));
+        }
+        if stat.st_size > 0 {
+            return Ok(stat.st_size as usize);
+        }
+        if Instant::now() >= deadline {
+            unsafe { close(fd) };
+            return Err(Error::TimedOut);
+        }
+        spin_wait(Duration::from_micros(100));
+    }
+}
+
+// The address `mmap` is asked for, see `RingConfig::base_address`
+fn base_hint(base: Option<usize>) -> *mut libc::c_void {
+    base.map_or(ptr::null_mut(), |base| base as *mut libc::c_void)
+}
+
+// Linux can refuse a taken base outright; elsewhere the base is only a hint
+fn base_flags(base: Option<usize>) -> libc::c_int {
+    match base {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        Some(_) => libc::MAP_FIXED_NOREPLACE,
+        _ => 0,
+    }
+}
+
+// Undo a mapping of `size` bytes at `addr` that didn't land on the `base` asked for
+// (a kernel ignoring `MAP_FIXED_NOREPLACE` and treating it as a hint does that too)
+unsafe fn check_base(addr: *mut libc::c_void, size: usize, base: Option<usize>) -> io::Result<()> {
+    match base {
+        Some(base) if addr as usize != base => {
+            munmap(addr, size);
+            Err(io::Error::from_raw_os_error(libc::EEXIST))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "magic-ring"))]
+unsafe fn map_segment(
+    fd: libc::c_int,
+    data_offset: usize,
+    buffer_size: usize,
+    flags: libc::c_int,
+    base: Option<usize>,
+) -> io::Result<*mut libc::c_void> {
+    let size = data_offset + buffer_size;
+    let addr = mmap(base_hint(base), size, PROT_READ | PROT_WRITE, MAP_SHARED | flags | base_flags(base), fd, 0);
+    if addr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    check_base(addr, size, base)?;
+    Ok(addr)
+}
+
+// Map the segment (whatever precedes the data region at `data_offset`, then the
+// data), then the data region once more right after it, so accesses running past
+// the end of the ring continue at its start
+#[cfg(feature = "magic-ring")]
+unsafe fn map_segment(
+    fd: libc::c_int,
+    data_offset: usize,
+    buffer_size: usize,
+    flags: libc::c_int,
+    fixed: Option<usize>,
+) -> io::Result<*mut libc::c_void> {
+    use libc::{MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, PROT_NONE};
+
+    // Reserve the whole range first so nothing else lands between the two views
+    let segment_size = data_offset + buffer_size;
+    let reserve_flags = MAP_PRIVATE | MAP_ANONYMOUS | base_flags(fixed);
+    let base = mmap(base_hint(fixed), segment_size + buffer_size, PROT_NONE, reserve_flags, -1, 0);
+    if base == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    check_base(base, segment_size + buffer_size, fixed)?;
+
+    let primary = mmap(base, segment_size, PROT_READ | PROT_WRITE, MAP_SHARED | MAP_FIXED | flags, fd, 0);
+    let mirror = mmap(
+        (base as *mut u8).add(segment_size) as *mut libc::c_void,
+        buffer_size,
+        PROT_READ | PROT_WRITE,
+        MAP_SHARED | MAP_FIXED | flags,
+        fd,
+        data_offset as libc::off_t,
+    );
+    if primary == libc::MAP_FAILED || mirror == libc::MAP_FAILED {
+        let err = io::Error::last_os_error();
+        munmap(base, segment_size + buffer_size);
+        return Err(err);
+    }
+    Ok(base)
+}
+
+fn producer(_id: usize, ring_buffer: Arc<SharedRingBuffer>) {
+    #[cfg(feature = "affinity")]
+    if let Err(e) = pin_to_core(PRODUCER_FIRST_CORE + _id) {
+        eprintln!("producer {}: pinning to core {}: {}", _id, PRODUCER_FIRST_CORE + _id, e);
+    }
+
+    let mut message_count = 0;
+    loop {
+        let message = format!("Received {}", message_count).into_bytes();
+        if ring_buffer.write_message(&message).is_ok() {
+            message_count += 1;
+        }
+    }
+}
+
+fn consumer(_id: usize, ring_buffer: Arc<SharedRingBuffer>) {
+    #[cfg(feature = "affinity")]
+    if let Err(e) = pin_to_core(CONSUMER_FIRST_CORE + _id) {
+        eprintln!("consumer {}: pinning to core {}: {}", _id, CONSUMER_FIRST_CORE + _id, e);
+    }
+
+    loop {
+        if let Ok(message) = ring_buffer.read_message() {
+            println!("{}", String::from_utf8_lossy(&message));
+        }
+    }
+}
+
+// Pin the calling thread to a single core (opt-in, `affinity` feature)
+#[cfg(feature = "affinity")]
+fn pin_to_core(core: usize) -> Result<(), Error> {
+    // `CPU_SET` panics for a core the set has no bit for
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(Error::InvalidConfig("core is beyond CPU_SETSIZE"));
+    }
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(core, &mut set);
+        // pid 0 targets the calling thread
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(Error::Os("Failed to set thread affinity", io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+// Low-latency CPU spin loop
+#[inline(always)]
+fn spin_wait(duration: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        std::hint::spin_loop();
+    }
+}
+
+// `spin_wait` for `WaitStrategy::Monitor`: instead of `PAUSE`, nap the core with
+// `UMWAIT` (Intel, WAITPKG) or `MWAITX` (AMD) armed on `watch`, or `WFE` on
+// aarch64, and return early once `watch` changes. A nap lasts at most
+// `NAP_CYCLES` TSC ticks on x86 (and until the next timer event, which Linux
+// sends every 100us, on aarch64), so the deadline is still checked in time.
+// CPUs without these instructions spin as before.
+fn monitor_wait(watch: Option<&AtomicU64>, duration: Duration) {
+    let start = Instant::now();
+    let watch = watch.map(|word| (word, word.load(Ordering::Relaxed)));
+    while start.elapsed() < duration {
+        if watch.is_some_and(|(word, seen)| word.load(Ordering::Relaxed) != seen) {
+            return;
+        }
+        nap(watch);
+    }
+}
+
+// The ways an x86 CPU lets user code nap, best first
+#[cfg(target_arch = "x86_64")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum X86Nap {
+    Umwait, // WAITPKG: `UMONITOR`/`UMWAIT`
+    Mwaitx, // `MONITORX`/`MWAITX`
+    Pause,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl X86Nap {
+    // Probed with `CPUID` once per process
+    fn get() -> Self {
+        use std::arch::x86_64::{__cpuid, __cpuid_count};
+        static NAP: OnceLock<X86Nap> = OnceLock::new();
+        *NAP.get_or_init(|| {
+            if __cpuid(0).eax >= 7 && __cpuid_count(7, 0).ecx & (1 << 5) != 0 {
+                X86Nap::Umwait
+            } else if __cpuid(0x8000_0000).eax >= 0x8000_0001 && __cpuid(0x8000_0001).ecx & (1 << 29) != 0 {
+                X86Nap::Mwaitx
+            } else {
+                X86Nap::Pause
+            }
+        })
+    }
+}
+
+// One nap of `monitor_wait`, cut short by a store to `watch`'s word while it
+// still holds the value seen; without a word it just sleeps out the nap
+#[cfg(target_arch = "x86_64")]
+fn nap(watch: Option<(&AtomicU64, u64)>) {
+    use std::arch::asm;
+    const NAP_CYCLES: u64 = 2_000; // Under a microsecond at the usual TSC rates
+    let idle = AtomicU64::new(0);
+    let (word, seen) = watch.unwrap_or((&idle, 0));
+    match X86Nap::get() {
+        X86Nap::Umwait => unsafe {
+            let deadline = std::arch::x86_64::_rdtsc().wrapping_add(NAP_CYCLES);
+            asm!("umonitor {}", in(reg) word.as_ptr(), options(nostack, preserves_flags));
+            // Re-check after arming, or a store just before it would go unnoticed
+            if word.load(Ordering::Relaxed) == seen {
+                // Control 1 picks the lighter C0.1 state, for the faster wakeup
+                asm!(
+                    "umwait {:e}",
+                    in(reg) 1u32,
+                    in("eax") deadline as u32,
+                    in("edx") (deadline >> 32) as u32,
+                    options(nostack),
+                );
+            }
+        },
+        X86Nap::Mwaitx => unsafe {
+            asm!("monitorx", in("rax") word.as_ptr(), in("ecx") 0, in("edx") 0, options(nostack, preserves_flags));
+            if word.load(Ordering::Relaxed) == seen {
+                // The timeout goes in EBX, which LLVM won't hand out, hence the swap;
+                // EAX 0xf0 stays in C0 and ECX bit 1 enables the timeout
+                asm!(
+                    "xchg {cycles}, rbx",
+                    "mwaitx",
+                    "xchg {cycles}, rbx",
+                    cycles = inout(reg) NAP_CYCLES => _,
+                    in("eax") 0xf0u32,
+                    in("ecx") 2u32,
+                    options(nostack, preserves_flags),
+                );
+            }
+        },
+        X86Nap::Pause => std::hint::spin_loop(),
+    }
+}
+
+// One nap of `monitor_wait`: `LDXR` arms the exclusive monitor on `watch`'s
+// word, and a store to it by another core raises the event `WFE` waits for
+#[cfg(target_arch = "aarch64")]
+fn nap(watch: Option<(&AtomicU64, u64)>) {
+    use std::arch::asm;
+    let Some((word, seen)) = watch else {
+        return std::hint::spin_loop();
+    };
+    let current: u64;
+    unsafe {
+        asm!("ldxr {}, [{}]", out(reg) current, in(reg) word.as_ptr(), options(nostack, preserves_flags));
+        if current == seen {
+            asm!("wfe", options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn nap(_watch: Option<(&AtomicU64, u64)>) {
+    std::hint::spin_loop();
+}
+
+// Cross-process self-check: a child process (this binary, `e2e-producer` role)
+// attaches to the segment and writes `count` numbered messages, which this
+// process must receive complete and in order
+fn end_to_end(name: &RingName, count: usize) {
+    let ring_buffer = SharedRingBuffer::new(name).expect("Failed to create shared ring buffer");
+    let too_large = vec![0; ring_buffer.max_message_len() + 1];
+    assert!(
+        matches!(ring_buffer.write_message(&too_large), Err(Error::MessageTooLarge { .. })),
+        "a message one byte over max_message_len was accepted"
+    );
+    let exe = std::env::current_exe().expect("Failed to locate own executable");
+    let mut child = Command::new(exe)
+        .args(["e2e-producer", &count.to_string()])
+        .spawn()
+        .expect("Failed to spawn producer process");
+
+    for expected in 0..count {
+        // Don't block forever on a ring whose producer is gone
+        while ring_buffer.is_empty() {
+            if let Some(status) = child.try_wait().expect("Failed to poll producer process") {
+                if ring_buffer.is_empty() {
+                    panic!("producer exited ({}) after {} of {} messages", status, expected, count);
+                }
+            }
+        }
+        let message = ring_buffer.read_message().expect("Failed to read message");
+        assert_eq!(message, e2e_message(expected), "message {} missing or out of order", expected);
+    }
+
+    let status = child.wait().expect("Failed to wait for producer process");
+    assert!(status.success(), "producer process failed: {}", status);
+    assert!(ring_buffer.is_empty(), "producer wrote more than {} messages", count);
+    ring_buffer.validate().expect("Shared ring buffer failed validation");
+    println!("e2e: received {} messages in order from another process", count);
+}
+
+// Ownership self-check: `RACE_CREATORS` processes (this binary, `race-creator`
+// role) create the same ring at once, and exactly one of them may end up owning
+// it; the others must be refused rather than re-initialize it under the winner.
+// Once the winner is gone the ring can be created again.
+fn create_race(name: &RingName) {
+    let exe = std::env::current_exe().expect("Failed to locate own executable");
+    let racers: Vec<_> = (0..RACE_CREATORS)
+        .map(|_| Command::new(&exe).arg("race-creator").spawn().expect("Failed to spawn creator process"))
+        .collect();
+    let mut owners = 0;
+    for mut racer in racers {
+        let status = racer.wait().expect("Failed to wait for creator process");
+        match status.code() {
+            Some(0) => owners += 1,
+            Some(RACE_REFUSED) => {}
+            _ => panic!("creator process failed: {}", status),
+        }
+    }
+    assert_eq!(owners, 1, "{} of {} racing creators own the ring", owners, RACE_CREATORS);
+    // The winner's claim must have gone with it
+    SharedRingBuffer::new(name).expect("Failed to create the ring after its owner exited");
+    println!("create-race: exactly one of {} racing creators owns the ring", RACE_CREATORS);
+}
+
+// Exit status of a `race-creator` that was refused the ring
+const RACE_REFUSED: i32 = 2;
+
+// One racer of `create_race`: holds the ring for `RACE_HOLD` if it wins, so every
+// other racer finds a live owner, and exits with `RACE_REFUSED` if it loses
+fn race_creator(name: &RingName) {
+    match SharedRingBuffer::new(name) {
+        Ok(_ring) => thread::sleep(RACE_HOLD),
+        Err(Error::Os(_, e)) if e.raw_os_error() == Some(libc::EEXIST) => std::process::exit(RACE_REFUSED),
+        Err(e) => panic!("Failed to create shared ring buffer: {}", e),
+    }
+}
+
+fn e2e_producer(name: &RingName, count: usize) {
+    let ring_buffer = SharedRingBuffer::open(name, OPEN_TIMEOUT).expect("Failed to open shared ring buffer");
+    for i in 0..count {
+        ring_buffer.write_message(&e2e_message(i)).expect("Failed to write message");
+    }
+}
+
+// Message `i` of the `e2e` self-check: its index, except that every 1000th one is
+// an empty tick so zero-length messages get checked too, and the two after it are
+// padded to the default slot's payload and one byte short of it
+fn e2e_message(i: usize) -> Vec<u8> {
+    let mut message = i.to_string().into_bytes();
+    match i % 1000 {
+        0 => message.clear(),
+        1 => message.resize(SLOT_SIZE - 4, b' '),
+        2 => message.resize(SLOT_SIZE - 5, b' '),
+        _ => {}
+    }
+    message
+}
+
+// Corrupt-segment self-check: `rounds` times, scribble pseudo-random bytes over
+// the indices and the slot they point at, as a crashed or hostile process could,
+// and read. `read_message` must never panic and never hand back more than a
+// slot's payload: it either returns a message or fails cleanly with
+// `Error::Corrupt`, or with `Error::Lapped` when the write index is one to two laps
+// ahead. Each round also tries a length prefix that fits, so messages do come
+// back. Pass the printed seed (`fuzz-read <seed>`) to replay a failure.
+fn fuzz_read(name: &RingName, seed: Option<u64>, rounds: usize) {
+    let ring_buffer = RingConfig::new(name).capacity(64).build().expect("Failed to create shared ring buffer");
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(1, |t| t.as_nanos() as u64)
+    });
+    println!("fuzz-read: seed {}", seed);
+    // xorshift64*: enough to spread the bytes around, and replayable
+    let mut state = seed | 1;
+    let mut next = move || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    };
+
+    let (buffer_size, slot_size) = (ring_buffer.buffer_size as u64, ring_buffer.slot_size);
+    let mut slot = vec![0u8; slot_size];
+    let (mut messages, mut corrupt, mut lapped) = (0, 0, 0);
+    for _ in 0..rounds {
+        // Half the time a whole slot's index, else any index at all
+        let read_idx = match next() % 2 {
+            0 => next() % (2 * buffer_size) / slot_size as u64 * slot_size as u64,
+            _ => next(),
+        };
+        // Up to two laps ahead (never equal, or the read would wait for a producer),
+        // else anywhere at all, which is mostly behind
+        let distance = match next() % 4 {
+            0 => next() | 1,
+            _ => 1 + next() % (2 * buffer_size),
+        };
+        let write_idx = read_idx.wrapping_add(distance);
+        slot.iter_mut().for_each(|byte| *byte = next() as u8);
+        if next() % 2 == 0 {
+            let len = (next() % slot_size as u64) as u32;
+            slot[..4].copy_from_slice(&ring_buffer.endianness.encode(len));
+        }
+
+        let start = ring_buffer.slot_offset(read_idx);
+        let len = slot_size.min(ring_buffer.buffer_size - start);
+        unsafe { ptr::copy_nonoverlapping(slot.as_ptr(), ring_buffer.buffer.add(start), len) };
+        ring_buffer.header().read_idx.store(read_idx, Ordering::Relaxed);
+        ring_buffer.header().write_idx.store(write_idx, Ordering::Release);
+
+        match ring_buffer.read_message() {
+            Ok(message) => {
+                assert!(distance <= buffer_size, "seed {}: read a message {} bytes behind the writer", seed, distance);
+                assert!(
+                    message.len() <= ring_buffer.max_message_len(),
+                    "seed {}: {} byte message out of a {} byte slot",
+                    seed,
+                    message.len(),
+                    slot_size
+                );
+                messages += 1;
+            }
+            Err(Error::Lapped { .. }) if distance > buffer_size && distance <= 2 * buffer_size => lapped += 1,
+            Err(Error::Corrupt(_)) if distance <= buffer_size || distance > 2 * buffer_size => corrupt += 1,
+            Err(e) => panic!("seed {}: read {} bytes behind the writer failed with {}", seed, distance, e),
+        }
+    }
+    // Leave consistent indices behind for any later reader
+    ring_buffer.header().read_idx.store(0, Ordering::Relaxed);
+    ring_buffer.header().write_idx.store(0, Ordering::Release);
+    println!(
+        "fuzz-read: {} rounds, {} messages read, {} rejected as corrupt, {} as lapped",
+        rounds, messages, corrupt, lapped
+    );
+}
+
+// Damage the `fault-inject` self-check plants in the ring between a write and the
+// read expected to catch it, as a torn write or a stray process would leave it
+#[derive(Debug, Clone, Copy)]
+enum Fault {
+    FlipByte(usize), // Invert byte `n` of the oldest unread slot, its length prefix included
+    FlipLength,      // Invert the length prefix of the oldest unread slot
+    Misalign(u64),   // Push `read_idx` this many bytes past its slot boundary
+}
+
+impl Fault {
+    fn inject(self, ring: &SharedRingBuffer) {
+        let read_idx = ring.header().read_idx.load(Ordering::Relaxed);
+        let slot = unsafe { ring.buffer.add(ring.slot_offset(read_idx)) };
+        let flip = |offset: usize| unsafe { *slot.add(offset) = !*slot.add(offset) };
+        match self {
+            Fault::FlipByte(n) => flip(n),
+            Fault::FlipLength => (0..ring.framing.overhead()).for_each(flip),
+            Fault::Misalign(by) => ring.header().read_idx.store(read_idx + by, Ordering::Relaxed),
+        }
+    }
+}
+
+// Fault-injection self-check: plant each `Fault` in turn and check the read that
+// meets it reacts as documented, `Error::Corrupt` on a plain ring and skipped to
+// the next good message with `RingConfig::resync_on_corruption`. Also stalls a
+// write between filling its slot and publishing it, and abandons another, neither
+// of which the consumer may see.
+fn fault_inject(name: &RingName) {
+    // A fault the read doesn't get past stays at `read_idx`: drop what's unread
+    let skip_unread = |ring: &SharedRingBuffer| {
+        ring.header().read_idx.store(ring.header().write_idx.load(Ordering::Acquire), Ordering::Relaxed)
+    };
+    let mut faults = 0;
+
+    let ring = RingConfig::new(name).capacity(FAULT_SLOTS).build().expect("Failed to create shared ring buffer");
+    let payload = ring.framing.overhead() + LenCrc::SIZE;
+    ring.write_message_framed::<LenCrc>(b"flipped", 0).unwrap();
+    Fault::FlipByte(payload).inject(&ring);
+    let read = ring.read_message_framed::<LenCrc>();
+    assert!(matches!(read, Err(Error::Corrupt(_))), "a flipped payload byte passed its CRC");
+    ring.write_message(b"torn").unwrap();
+    Fault::FlipLength.inject(&ring);
+    assert!(matches!(ring.read_message(), Err(Error::Corrupt(_))), "a flipped length prefix was read");
+    skip_unread(&ring);
+    ring.write_message(b"misread").unwrap();
+    Fault::Misalign(3).inject(&ring);
+    assert!(matches!(ring.read_message(), Err(Error::Corrupt(_))), "a read index off its slot boundary was read");
+    skip_unread(&ring);
+    faults += 3;
+
+    let mut stalled = ring.reserve(7).unwrap();
+    stalled.copy_from_slice(b"stalled");
+    assert_eq!(ring.readable(), 0, "a write was visible before it was published");
+    stalled.publish();
+    assert_eq!(ring.read_message().unwrap(), b"stalled");
+    ring.abandon_slot(ring.reserve(9).unwrap()).unwrap();
+    ring.write_message(b"after").unwrap();
+    assert_eq!(ring.read_message().unwrap(), b"after", "an abandoned slot wasn't skipped");
+    faults += 2;
+    drop(ring);
+
+    let ring = RingConfig::new(name)
+        .capacity(FAULT_SLOTS)
+        .resync_on_corruption(true)
+        .build()
+        .expect("Failed to create shared ring buffer");
+    let slot_size = ring.slot_size as u64;
+    ring.write_message_framed::<LenMagicCrc>(b"lost", 0).unwrap();
+    ring.write_message_framed::<LenMagicCrc>(b"kept", 0).unwrap();
+    Fault::FlipByte(ring.framing.overhead()).inject(&ring);
+    assert_eq!(ring.read_message_framed::<LenMagicCrc>().unwrap().1, b"kept", "no resync past a broken magic");
+    for fault in [Fault::FlipLength, Fault::Misalign(3)] {
+        ring.write_message(b"lost").unwrap();
+        ring.write_message(b"kept").unwrap();
+        fault.inject(&ring);
+        assert_eq!(ring.read_message().unwrap(), b"kept", "no resync past {:?}", fault);
+    }
+    // A slot each for the magic and the length, the rest of a slot after the misaligned index
+    assert_eq!(ring.resynced_bytes(), 3 * slot_size - 3);
+    faults += 3;
+    println!("fault-inject: {} faults, each caught as documented", faults);
+}
+
+// The ring `on_signal` writes to for `signal_write`, and what the write returned
+static SIGNAL_RING: AtomicPtr<SharedRingBuffer> = AtomicPtr::new(ptr::null_mut());
+static SIGNAL_OUTCOME: AtomicU8 = AtomicU8::new(SIGNAL_NOT_RUN);
+const SIGNAL_NOT_RUN: u8 = 0;
+const SIGNAL_WRITTEN: u8 = 1;
+const SIGNAL_FULL: u8 = 2;
+const SIGNAL_FAILED: u8 = 3;
+
+// SIGUSR1 handler of `signal_write`: only atomics and `try_write_message`
+extern "C" fn on_signal(_: libc::c_int) {
+    let Some(ring) = (unsafe { SIGNAL_RING.load(Ordering::Acquire).as_ref() }) else {
+        return;
+    };
+    let outcome = match ring.try_write_message(b"from a signal handler") {
+        Ok(_) => SIGNAL_WRITTEN,
+        Err(Error::Full) => SIGNAL_FULL,
+        Err(_) => SIGNAL_FAILED,
+    };
+    SIGNAL_OUTCOME.store(outcome, Ordering::Release);
+}
+
+// Signal-handler self-check: raise SIGUSR1 with `on_signal` installed, so
+// `try_write_message` runs in the handler, once on an empty ring (the message must
+// come back) and once on a full one (`Error::Full`, nothing written)
+fn signal_write(name: &RingName) {
+    let ring = SharedRingBuffer::new(name).expect("Failed to create shared ring buffer");
+    SIGNAL_RING.store(&ring as *const SharedRingBuffer as *mut SharedRingBuffer, Ordering::Release);
+    let previous = unsafe { libc::signal(libc::SIGUSR1, on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t) };
+    assert_ne!(previous, libc::SIG_ERR, "Failed to install the SIGUSR1 handler");
+    let raise = || {
+        SIGNAL_OUTCOME.store(SIGNAL_NOT_RUN, Ordering::Relaxed);
+        // The handler runs on this thread before `raise` returns
+        assert_eq!(unsafe { libc::raise(libc::SIGUSR1) }, 0, "Failed to raise SIGUSR1");
+        SIGNAL_OUTCOME.load(Ordering::Acquire)
+    };
+
+    assert_eq!(raise(), SIGNAL_WRITTEN, "try_write_message failed in the signal handler");
+    assert_eq!(ring.read_message().unwrap(), b"from a signal handler");
+    while ring.try_write_message(b"filler").is_ok() {}
+    let filled = ring.readable();
+    assert_eq!(raise(), SIGNAL_FULL, "try_write_message didn't report a full ring in the signal handler");
+    assert_eq!(ring.readable(), filled, "the signal handler wrote to a full ring");
+
+    unsafe { libc::signal(libc::SIGUSR1, libc::SIG_DFL) };
+    SIGNAL_RING.store(ptr::null_mut(), Ordering::Release);
+    println!("signal: try_write_message from a SIGUSR1 handler wrote, then found the ring full");
+}
+
+// Capacity self-check: every slot takes a message, with no slot kept back to tell a
+// full ring from an empty one. Fill the ring, check one more write is refused,
+// read one message and check the write then goes through, all in order. Layouts
+// of fewer than two slots must be refused by `build`, two accepted.
+fn capacity_check(name: &RingName) {
+    // Two slots make one `DATA_OFFSET`, so the smallest ring can be mirrored too
+    let slot_size = DATA_OFFSET / 2;
+    for capacity in [0, 1] {
+        match RingConfig::new(name).slot_size(slot_size).capacity(capacity).build() {
+            Err(Error::InvalidConfig("buffer_size must hold at least two slots")) => {}
+            Ok(_) => panic!("a ring of {} slots was built", capacity),
+            Err(e) => panic!("a ring of {} slots failed with {} instead of too few slots", capacity, e),
+        }
+    }
+    let smallest = RingConfig::new(name).slot_size(slot_size).capacity(2).build().expect("Failed to build a two-slot ring");
+    assert_eq!(smallest.capacity(), 2);
+    drop(smallest);
+
+    let ring = RingConfig::new(name)
+        .capacity(CAPACITY_SLOTS)
+        .full(FullPolicy::Drop)
+        .build()
+        .expect("Failed to create shared ring buffer");
+    for i in 0..CAPACITY_SLOTS {
+        assert!(!ring.is_full(), "ring full after {} of {} slots", i, CAPACITY_SLOTS);
+        ring.write_message(i.to_string().as_bytes()).expect("Failed to write message");
+    }
+    assert!(ring.is_full(), "ring not full with all {} slots written", CAPACITY_SLOTS);
+    assert_eq!(ring.readable(), CAPACITY_SLOTS);
+    assert!(matches!(ring.write_message(b"one too many"), Err(Error::Full)), "a write past capacity went through");
+
+    assert_eq!(ring.read_message().unwrap(), b"0");
+    assert!(!ring.is_full(), "ring still full after a read");
+    ring.write_message(CAPACITY_SLOTS.to_string().as_bytes()).expect("Failed to write message into the freed slot");
+    for i in 1..=CAPACITY_SLOTS {
+        assert_eq!(ring.read_message().unwrap(), i.to_string().as_bytes(), "message {} out of order", i);
+    }
+    assert!(ring.is_empty(), "messages left over after reading them all");
+    println!("capacity: 0 and 1 slots refused, {} slots filled, the next write refused until one was read", CAPACITY_SLOTS);
+}
+
+// Two-ring self-check: rings under different names are separate segments. Run a
+// producer and a consumer on each at once, check each consumer gets exactly its
+// own ring's messages in order, and that dropping the rings removes both names.
+fn two_rings(name: &RingName, count: usize) {
+    let other = RingName::try_from(format!("{}.other", name).as_str()).expect("Invalid second ring name");
+    let rings = [name, &other].map(|name| Arc::new(SharedRingBuffer::new(name).expect("Failed to create shared ring buffer")));
+    let threads: Vec<_> = (0..rings.len())
+        .flat_map(|ring_no| {
+            let producer = {
+                let ring = Arc::clone(&rings[ring_no]);
+                thread::spawn(move || {
+                    for i in 0..count {
+                        ring.write_message(format!("{}:{}", ring_no, i).as_bytes()).expect("Failed to write message");
+                    }
+                })
+            };
+            let consumer = {
+                let ring = Arc::clone(&rings[ring_no]);
+                thread::spawn(move || {
+                    for i in 0..count {
+                        let message = ring.read_message().expect("Failed to read message");
+                        assert_eq!(message, format!("{}:{}", ring_no, i).as_bytes(), "cross-talk on ring {}", ring_no);
+                    }
+                })
+            };
+            [producer, consumer]
+        })
+        .collect();
+    for thread in threads {
+        thread.join().expect("Two-ring thread panicked");
+    }
+    assert!(rings.iter().all(|ring| ring.is_empty()), "a ring holds messages it wasn't sent");
+
+    drop(rings);
+    for name in [name, &other] {
+        match SharedRingBuffer::open(name, Duration::ZERO) {
+            Err(Error::NotFound(_)) => {}
+            Ok(_) => panic!("{} still exists after its ring was dropped", name),
+            Err(e) => panic!("Opening the dropped {} failed with {} instead of not found", name, e),
+        }
+    }
+    println!("two-rings: {} messages each through {} and {}, no cross-talk, both removed", count, name, other);
+}
+
+// Index-wrap self-check: start both indices half a ring short of `u64::MAX`, so
+// `write_idx` wraps to 0 while `read_idx` hasn't, then fill the ring across the
+// wrap and read it back, and keep writing and reading past it. The full/empty
+// checks and slot offsets must carry on as if the counters hadn't wrapped.
+fn index_wrap(name: &RingName) {
+    let ring = RingConfig::new(name).capacity(WRAP_SLOTS).build().expect("Failed to create shared ring buffer");
+    let start = 0u64.wrapping_sub((WRAP_SLOTS / 2 * ring.slot_size) as u64);
+    ring.header().read_idx.store(start, Ordering::Relaxed);
+    ring.header().write_idx.store(start, Ordering::Release);
+
+    for i in 0..WRAP_SLOTS {
+        ring.write_message(i.to_string().as_bytes()).expect("Failed to write message");
+    }
+    assert!(ring.header().write_idx.load(Ordering::Relaxed) < start, "write_idx didn't wrap");
+    assert!(ring.is_full(), "ring not full with all {} slots written across the wrap", WRAP_SLOTS);
+    assert_eq!(ring.readable(), WRAP_SLOTS);
+    for i in 0..WRAP_SLOTS {
+        assert_eq!(ring.read_message().unwrap(), i.to_string().as_bytes(), "message {} lost across the wrap", i);
+    }
+    assert!(ring.is_empty(), "ring not empty after reading back across the wrap");
+
+    // Both indices past the wrap now; four more laps, half a ring at a time
+    for lap in 0..8 {
+        let batch: Vec<_> = (0..WRAP_SLOTS / 2).map(|i| format!("{}.{}", lap, i)).collect();
+        for message in &batch {
+            ring.write_message(message.as_bytes()).expect("Failed to write message");
+        }
+        for message in &batch {
+            assert_eq!(ring.read_message().unwrap(), message.as_bytes(), "message lost after the wrap");
+        }
+    }
+    assert!(ring.is_empty(), "ring not empty after the laps past the wrap");
+    println!("index-wrap: {} slots written and read across the u64 wrap, then 4 more laps", WRAP_SLOTS);
+}
+
+// OS-error self-check: an errno the ring has its own variant for must come back as
+// that variant, and any other one inside `Error::Os`, never a bare message. There's
+// no syscall layer to mock, so each failure is real: a segment and a ring file that
+// don't exist (`NotFound`), a ring file where not even root may create one
+// (`PermissionDenied`, Linux only), and a ring file larger than `off_t`, which
+// `ftruncate` refuses with `EINVAL`. A full `/dev/shm` can't be arranged here, so
+// `ENOSPC` only goes through the mapping in `Error::os`.
+fn os_errors(name: &RingName) {
+    let missing = RingName::try_from(format!("{}.missing", name).as_str()).expect("Invalid missing ring name");
+    let opened = SharedRingBuffer::open(&missing, Duration::ZERO);
+    assert!(matches!(opened, Err(Error::NotFound(_))), "opening a missing segment: {:?}", opened.err());
+    let no_dir = std::env::temp_dir().join("share_mem_no_such_dir").join("ring");
+    let built = RingConfig::new(name).backing_file(&no_dir).build();
+    assert!(matches!(built, Err(Error::NotFound(_))), "a ring file in a missing directory: {:?}", built.err());
+    #[cfg(target_os = "linux")]
+    {
+        let built = RingConfig::new(name).backing_file("/sys/share_mem_ring").build();
+        assert!(matches!(built, Err(Error::PermissionDenied(_))), "a ring file under /sys: {:?}", built.err());
+    }
+    if cfg!(target_pointer_width = "64") {
+        let path = std::env::temp_dir().join("share_mem_oversized_ring");
+        let built = RingConfig::new(name).backing_file(&path).capacity((usize::MAX / 2 + 1) / SLOT_SIZE).build();
+        let _ = std::fs::remove_file(&path);
+        match built {
+            Err(Error::Os(_, e)) if e.raw_os_error() == Some(libc::EINVAL) => {}
+            Err(e) => panic!("a ring file larger than off_t failed with {} instead of EINVAL", e),
+            Ok(_) => panic!("a ring file larger than off_t was built"),
+        }
+    }
+    let full = Error::os("Failed to size shared memory", io::Error::from_raw_os_error(libc::ENOSPC));
+    assert!(matches!(full, Error::OutOfSpace(_)), "ENOSPC mapped to {:?}", full);
+    println!("os-errors: NotFound, PermissionDenied and EINVAL came back as documented, ENOSPC maps to OutOfSpace");
+}
+
+// Spurious-wakeup self-check: a reader parked on an empty ring, then a writer parked
+// on a full one (`WaitStrategy::SpinThenPark`), is unparked over and over with
+// nothing changed, as a spurious wakeup or an `unpark` meant for something else
+// would end its wait. Each must go back to waiting, and return only once there
+// really is a message to read or a slot to write.
+fn spurious_wakeups(name: &RingName) {
+    let wait = WaitStrategy::SpinThenPark { max_spin_budget: Duration::ZERO, park: Duration::from_secs(10) };
+    let ring = Arc::new(RingConfig::new(name).wait(wait).build().expect("Failed to create shared ring buffer"));
+    let returned = Arc::new(AtomicBool::new(false));
+
+    let reader = {
+        let (ring, returned) = (Arc::clone(&ring), Arc::clone(&returned));
+        thread::spawn(move || {
+            let message = ring.read_message().expect("Failed to read message");
+            returned.store(true, Ordering::Release);
+            message
+        })
+    };
+    for _ in 0..SPURIOUS_WAKEUPS {
+        reader.thread().unpark();
+        thread::sleep(Duration::from_micros(100));
+    }
+    assert!(!returned.load(Ordering::Acquire), "a reader woken on an empty ring returned");
+    ring.write_message(b"real").expect("Failed to write message");
+    reader.thread().unpark();
+    assert_eq!(reader.join().expect("Reader thread panicked"), b"real");
+
+    returned.store(false, Ordering::Relaxed);
+    let capacity = ring.capacity();
+    for i in 0..capacity {
+        ring.write_message(i.to_string().as_bytes()).expect("Failed to write message");
+    }
+    let writer = {
+        let (ring, returned) = (Arc::clone(&ring), Arc::clone(&returned));
+        thread::spawn(move || {
+            ring.write_message(b"real").expect("Failed to write message");
+            returned.store(true, Ordering::Release);
+        })
+    };
+    for _ in 0..SPURIOUS_WAKEUPS {
+        writer.thread().unpark();
+        thread::sleep(Duration::from_micros(100));
+    }
+    assert!(!returned.load(Ordering::Acquire), "a writer woken on a full ring returned");
+    assert_eq!(ring.readable(), capacity, "a writer woken on a full ring wrote");
+    ring.read_message().expect("Failed to read message");
+    writer.thread().unpark();
+    writer.join().expect("Writer thread panicked");
+    for i in 1..capacity {
+        assert_eq!(ring.read_message().unwrap(), i.to_string().as_bytes());
+    }
+    assert_eq!(ring.read_message().unwrap(), b"real");
+    println!("spurious-wakeup: a parked reader and writer each ignored {} wakeups with nothing changed", SPURIOUS_WAKEUPS);
+}
+
+// Fan-in self-check: `FAN_IN_SENDERS` threads send `count` messages each through
+// clones of one `channel` `Sender` at once. However their sends interleave, the
+// receiver must get every sender's messages in the order sent, none missing,
+// none twice.
+fn fan_in(name: &RingName, count: usize) {
+    let (sender, receiver) = channel(RingConfig::new(name)).expect("Failed to create shared ring buffer");
+    let senders: Vec<_> = (0..FAN_IN_SENDERS)
+        .map(|sender_no| {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for seq in 0..count {
+                    sender.send(format!("{}:{}", sender_no, seq).as_bytes()).expect("Failed to send message");
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut next = [0; FAN_IN_SENDERS];
+    for _ in 0..FAN_IN_SENDERS * count {
+        let message = String::from_utf8(receiver.recv().expect("Failed to receive message")).expect("Not UTF-8");
+        let (sender_no, seq) = message.split_once(':').expect("Message without a sender");
+        let (sender_no, seq): (usize, usize) = (sender_no.parse().unwrap(), seq.parse().unwrap());
+        assert_eq!(seq, next[sender_no], "sender {}: message {} arrived in place of {}", sender_no, seq, next[sender_no]);
+        next[sender_no] += 1;
+    }
+    for sender in senders {
+        sender.join().expect("Sender thread panicked");
+    }
+    assert!(matches!(receiver.try_recv(), Ok(None)), "messages left over after every sender's last");
+    println!("fan-in: {} senders, {} messages each, every sender's in order with no gaps", FAN_IN_SENDERS, count);
+}
+
+// Migrate self-check: queue two full-size messages on a ring with a user header,
+// for each framing, `migrate` it to one twice the size, and check the messages and
+// the header come across unchanged with nothing left behind.
+fn migrate_check(name: &RingName) {
+    let new_name = RingName::try_from(format!("{}.migrated", name).as_str()).expect("Invalid migrated ring name");
+    let framings =
+        [Framing::LengthPrefixed(PrefixWidth::U32), Framing::LengthPrefixed(PrefixWidth::U16), Framing::Fixed(64)];
+    for framing in framings {
+        let mut old = RingConfig::new(name)
+            .framing(framing)
+            .user_header(MIGRATE_HEADER.len())
+            .build()
+            .expect("Failed to create shared ring buffer");
+        old.user_header_mut().copy_from_slice(MIGRATE_HEADER);
+        let len = match framing {
+            Framing::Fixed(len) => len,
+            Framing::LengthPrefixed(_) => old.max_message_len(),
+        };
+        let messages = [vec![1u8; len], vec![2u8; len]];
+        for message in &messages {
+            old.write_message(message).expect("Failed to write message");
+        }
+
+        let new = SharedRingBuffer::migrate(&old, &new_name, 2 * old.capacity())
+            .unwrap_or_else(|e| panic!("Migrating a {:?} ring failed with {}", framing, e));
+        assert_eq!(new.max_message_len(), old.max_message_len(), "{:?}: message limit changed", framing);
+        assert_eq!(new.user_header(), MIGRATE_HEADER, "{:?}: user header not carried over", framing);
+        for message in &messages {
+            assert_eq!(&new.read_message().unwrap(), message, "{:?}: message changed in migration", framing);
+        }
+        assert!(old.is_empty() && new.is_empty(), "{:?}: messages left over after migrating", framing);
+    }
+    println!("migrate: {} framings moved with their messages and user header", framings.len());
+}
+
+// The producer writes exactly `count` messages and then marks the ring done; the
+// consumer reads until it sees the flag with nothing left unread, and checks that
+// it got every message without agreeing on a count or a timeout beforehand
+fn drain_then_close(name: &RingName, count: usize) {
+    let ring_buffer = Arc::new(SharedRingBuffer::new(name).expect("Failed to create shared ring buffer"));
+    let producer = {
+        let ring_buffer = Arc::clone(&ring_buffer);
+        thread::spawn(move || {
+            for i in 0..count {
+                ring_buffer.write_message(i.to_string().as_bytes()).expect("Failed to write message");
+            }
+            ring_buffer.mark_done();
+        })
+    };
+
+    let mut received = 0;
+    loop {
+        // Check the flag before the ring, so a message written just before it is still read
+        let done = ring_buffer.is_done();
+        if ring_buffer.is_empty() {
+            if done {
+                break;
+            }
+            spin_wait(Duration::from_micros(1));
+            continue;
+        }
+        ring_buffer.read_message().expect("Failed to read message");
+        received += 1;
+    }
+
+    producer.join().expect("Producer thread panicked");
+    assert_eq!(received, count, "consumer saw the done flag before draining every message");
+    println!("drain: read all {} messages before the producer's done flag", count);
+}
+
+// Baseline numbers for the ring: single-producer/single-consumer throughput with
+// full-payload messages, then one-way latency percentiles for messages sent one
+// at a time into an otherwise empty ring, then throughput with `BENCH_PRODUCERS`
+// threads sending through one `channel`, then round-trip latency through a second
+// ring an echo thread answers on
+fn bench(name: &RingName) {
+    let ring_buffer = Arc::new(SharedRingBuffer::new(name).expect("Failed to create shared ring buffer"));
+    let payload = vec![0xA5u8; ring_buffer.slot_size - 4];
+
+    let consumer = {
+        let ring_buffer = Arc::clone(&ring_buffer);
+        thread::spawn(move || {
+            for _ in 0..BENCH_MESSAGES {
+                ring_buffer.read_message().expect("Failed to read message");
+            }
+        })
+    };
+    let start = Instant::now();
+    for _ in 0..BENCH_MESSAGES {
+        ring_buffer.write_message(&payload).expect("Failed to write message");
+    }
+    consumer.join().expect("Consumer thread panicked");
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "throughput: {:.0} msg/s, {:.1} MB/s payload ({} x {} bytes in {:.2}s, {}-byte cache line padding)",
+        BENCH_MESSAGES as f64 / elapsed,
+        (BENCH_MESSAGES * payload.len()) as f64 / elapsed / 1e6,
+        BENCH_MESSAGES,
+        payload.len(),
+        elapsed,
+        CACHE_LINE,
+    );
+
+    // Each message carries its send time relative to a shared origin
+    let origin = Instant::now();
+    let consumer = {
+        let ring_buffer = Arc::clone(&ring_buffer);
+        thread::spawn(move || {
+            let mut latencies = Vec::with_capacity(BENCH_SAMPLES);
+            for _ in 0..BENCH_SAMPLES {
+                let message = ring_buffer.read_message().expect("Failed to read message");
+                let received = origin.elapsed().as_nanos() as u64;
+                let sent = u64::from_le_bytes(message[..8].try_into().unwrap());
+                latencies.push(received - sent);
+            }
+            latencies
+        })
+    };
+    for _ in 0..BENCH_SAMPLES {
+        while !ring_buffer.is_empty() {
+            std::hint::spin_loop();
+        }
+        let sent = origin.elapsed().as_nanos() as u64;
+        ring_buffer.write_message(&sent.to_le_bytes()).expect("Failed to write message");
+    }
+    print_latencies("latency", consumer.join().expect("Consumer thread panicked"));
+    drop(ring_buffer);
+
+    let (sender, receiver) = channel(RingConfig::new(name)).expect("Failed to create shared ring buffer");
+    let per_producer = BENCH_MESSAGES / BENCH_PRODUCERS;
+    let start = Instant::now();
+    let producers: Vec<_> = (0..BENCH_PRODUCERS)
+        .map(|_| {
+            let (sender, payload) = (sender.clone(), payload.clone());
+            thread::spawn(move || {
+                for _ in 0..per_producer {
+                    sender.send(&payload).expect("Failed to send message");
+                }
+            })
+        })
+        .collect();
+    for _ in 0..per_producer * BENCH_PRODUCERS {
+        receiver.recv().expect("Failed to receive message");
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    for producer in producers {
+        producer.join().expect("Producer thread panicked");
+    }
+    println!(
+        "multi-producer throughput: {:.0} msg/s ({} producers x {} messages in {:.2}s)",
+        (per_producer * BENCH_PRODUCERS) as f64 / elapsed,
+        BENCH_PRODUCERS,
+        per_producer,
+        elapsed,
+    );
+    drop((sender, receiver));
+
+    // Ping on `name`, pong on a ring of its own; the echo thread sends each message back
+    let pong_name = RingName::try_from(format!("{}.pong", name).as_str()).expect("Invalid pong ring name");
+    let ping = Arc::new(SharedRingBuffer::new(name).expect("Failed to create shared ring buffer"));
+    let pong = Arc::new(SharedRingBuffer::new(&pong_name).expect("Failed to create shared ring buffer"));
+    let echo = {
+        let (ping, pong) = (Arc::clone(&ping), Arc::clone(&pong));
+        thread::spawn(move || {
+            for _ in 0..BENCH_SAMPLES {
+                let message = ping.read_message().expect("Failed to read message");
+                pong.write_message(&message).expect("Failed to write message");
+            }
+        })
+    };
+    let mut round_trips = Vec::with_capacity(BENCH_SAMPLES);
+    for i in 0..BENCH_SAMPLES as u64 {
+        let sent = Instant::now();
+        ping.write_message(&i.to_le_bytes()).expect("Failed to write message");
+        let message = pong.read_message().expect("Failed to read message");
+        round_trips.push(sent.elapsed().as_nanos() as u64);
+        assert_eq!(message, i.to_le_bytes(), "echo out of order");
+    }
+    echo.join().expect("Echo thread panicked");
+    print_latencies("round trip", round_trips);
+}
+
+fn print_latencies(what: &str, mut latencies: Vec<u64>) {
+    latencies.sort_unstable();
+    let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize];
+    println!(
+        "{}: p50 {}ns, p99 {}ns, p99.9 {}ns, max {}ns ({} samples)",
+        what,
+        percentile(0.50),
+        percentile(0.99),
+        percentile(0.999),
+        latencies[latencies.len() - 1],
+        latencies.len(),
+    );
+}
+
+// Usage: no argument runs producers and consumers in this process, `producer`
+// creates the segment and only produces, `consumer` attaches to it and only consumes,
+// `e2e` runs the cross-process self-check, `drain` the drain-then-close example,
+// `bench` prints throughput and latency numbers
+fn main() {
+    let name = RingName::try_from(SHM_NAME).expect("SHM_NAME is not a valid segment name");
+    let role = std::env::args().nth(1);
+    match role.as_deref() {
+        Some("e2e") => return end_to_end(&name, E2E_MESSAGES),
+        Some("drain") => return drain_then_close(&name, E2E_MESSAGES),
+        Some("bench") => return bench(&name),
+        Some("create-race") => return create_race(&name),
+        Some("race-creator") => return race_creator(&name),
+        Some("fuzz-read") => {
+            let seed = std::env::args().nth(2).and_then(|seed| seed.parse().ok());
+            return fuzz_read(&name, seed, FUZZ_ROUNDS);
+        }
+        Some("fault-inject") => return fault_inject(&name),
+        Some("capacity") => return capacity_check(&name),
+        Some("two-rings") => return two_rings(&name, E2E_MESSAGES),
+        Some("fan-in") => return fan_in(&name, E2E_MESSAGES),
+        Some("migrate") => return migrate_check(&name),
+        Some("index-wrap") => return index_wrap(&name),
+        Some("spurious-wakeup") => return spurious_wakeups(&name),
+        Some("os-errors") => return os_errors(&name),
+        Some("signal") => return signal_write(&name),
+        Some("e2e-producer") => {
+            let count = std::env::args().nth(2).and_then(|n| n.parse().ok()).unwrap_or(E2E_MESSAGES);
+            return e2e_producer(&name, count);
+        }
+        _ => {}
+    }
+    let num_producers = if role.as_deref() == Some("consumer") { 0 } else { NUM_PRODUCERS };
+    let num_consumers = if role.as_deref() == Some("producer") { 0 } else { NUM_CONSUMERS };
+
+    let ring_buffer = if num_producers > 0 {
+        SharedRingBuffer::new(&name).expect("Failed to create shared ring buffer")
+    } else {
+        SharedRingBuffer::open(&name, OPEN_TIMEOUT).expect("Failed to open shared ring buffer")
+    };
+    let ring_buffer = Arc::new(ring_buffer);
+    ring_buffer.validate().expect("Shared ring buffer failed validation");
+
+    let mut producer_handles = vec![];
+    for i in 0..num_producers {
+        let ring_buffer = Arc::clone(&ring_buffer);
+        producer_handles.push(thread::spawn(move || producer(i, ring_buffer)));
+    }
+
+    let mut consumer_handles = vec![];
+    for i in 0..num_consumers {
+        let ring_buffer = Arc::clone(&ring_buffer);
+        consumer_handles.push(thread::spawn(move || consumer(i, ring_buffer)));
+    }
+
+    thread::sleep(Duration::from_secs(10));
+    eprintln!("{:?}", ring_buffer.stats());
+}